@@ -27,6 +27,13 @@ echo "Deploying new API key to GitHub..."
 gh secret set RIOT_API_KEY --body "$RIOT_API_KEY" -R isak102/dodgetracker
 
 echo "API key deployed to GitHub."
+
+if systemctl is-active --quiet dodgetracker; then
+    echo "Reloading API key in the backend..."
+    sudo systemctl kill --signal=SIGHUP dodgetracker
+    echo "API key reloaded in the backend."
+fi
+
 echo "API key deployment complete."
 
 # TODO: deploy key to netlify
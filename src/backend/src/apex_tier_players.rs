@@ -1,20 +1,24 @@
 use std::collections::HashMap;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use anyhow::Result;
-use riven::consts::{PlatformRoute, QueueType};
+use riven::consts::PlatformRoute;
 use riven::models::league_v4::LeagueItem;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::{ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
-use tokio::try_join;
+use tokio::join;
 use tracing::info;
 use tracing::instrument;
 
-use crate::config::INSERT_CHUNK_SIZE;
 use crate::entities::apex_tier_players;
 use crate::entities::sea_orm_active_enums::RankTierEnum;
-use crate::riot_api::RIOT_API;
-use crate::util::with_timeout;
+use crate::ladder_recorder::LADDER_RECORDER;
+use crate::league_data_source::LeagueDataSource;
+use crate::rate_budget::Method;
+use crate::util::retry::retry_when_ready;
+use crate::{config, metrics};
+
+pub type ApiPlayers = HashMap<String, (LeagueItem, RankTierEnum)>;
 
 #[instrument(skip_all)]
 pub async fn get_players_from_db(
@@ -43,43 +47,80 @@ pub async fn get_players_from_db(
     Ok(result)
 }
 
-#[instrument(name = "apex_api", skip(region))]
+/// Whether `candidate` is a more recent ladder entry of the same player than `existing`. LP only
+/// goes down without playing a game (dodges and decay), so with equal games played the entry with
+/// less LP is the newer one.
+fn is_newer_entry(candidate: &LeagueItem, existing: &LeagueItem) -> bool {
+    let candidate_games = candidate.wins + candidate.losses;
+    let existing_games = existing.wins + existing.losses;
+
+    candidate_games > existing_games
+        || (candidate_games == existing_games && candidate.league_points < existing.league_points)
+}
+
+/// Merges the apex tier league lists into one map keyed by summoner ID. The lists are fetched
+/// concurrently, so a player that changes tier in between the requests (for example a Grandmaster
+/// that dodges below the Grandmaster LP threshold) can be in two lists at once. Apex tier LP is one
+/// continuous scale starting at 0 LP in Master, so the entries can be compared directly and only the
+/// newest one is kept.
+pub fn merge_apex_tiers<const N: usize>(tiers: [(Vec<LeagueItem>, RankTierEnum); N]) -> ApiPlayers {
+    let mut result: ApiPlayers = HashMap::new();
+
+    for (entries, tier) in tiers {
+        for entry in entries {
+            match result.get(&entry.summoner_id) {
+                Some((existing, _)) if !is_newer_entry(&entry, existing) => {}
+                _ => {
+                    result.insert(entry.summoner_id.clone(), (entry, tier.clone()));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[instrument(name = "apex_api", skip(source, region))]
 pub async fn get_players_from_api(
+    source: &dyn LeagueDataSource,
     region: PlatformRoute,
-) -> Result<(
-    HashMap<String, (LeagueItem, RankTierEnum)>,
-    (usize, usize, usize),
-)> {
+) -> Result<(ApiPlayers, (usize, usize, usize))> {
     let t1 = Instant::now();
 
-    let timeout = Duration::from_secs(10);
-    let master = with_timeout(
-        timeout,
-        RIOT_API
-            .league_v4()
-            .get_master_league(region, QueueType::RANKED_SOLO_5x5),
-    );
-    let grandmaster = with_timeout(
-        timeout,
-        RIOT_API
-            .league_v4()
-            .get_grandmaster_league(region, QueueType::RANKED_SOLO_5x5),
-    );
-    let challenger = with_timeout(
-        timeout,
-        RIOT_API
-            .league_v4()
-            .get_challenger_league(region, QueueType::RANKED_SOLO_5x5),
-    );
+    let config = config::get();
+    let route = region.to_string();
+    let get_league = |tier: RankTierEnum| {
+        retry_when_ready(
+            &config.retry,
+            config.timeouts.league,
+            || source.acquire_budget(&route, Method::League),
+            move || source.get_league(region, tier.clone()),
+        )
+    };
+    let master = get_league(RankTierEnum::Master);
+    let grandmaster = get_league(RankTierEnum::Grandmaster);
+    let challenger = get_league(RankTierEnum::Challenger);
 
     info!("Getting apex tier players from API...");
 
     let (master_result, grandmaster_result, challenger_result) =
-        try_join!(master, grandmaster, challenger)?;
+        join!(master, grandmaster, challenger);
+    for result in [&master_result, &grandmaster_result, &challenger_result] {
+        metrics::record_api_result("league", region, result);
+    }
     let master_result = master_result?;
     let grandmaster_result = grandmaster_result?;
     let challenger_result = challenger_result?;
 
+    if let Some(recorder) = LADDER_RECORDER.as_ref() {
+        recorder.record(
+            region,
+            &master_result,
+            &grandmaster_result,
+            &challenger_result,
+        );
+    }
+
     info!(
         perf = t1.elapsed().as_millis(),
         metric = "apex_api_query",
@@ -94,23 +135,11 @@ pub async fn get_players_from_api(
         challenger_result.entries.len(),
     );
 
-    let result: HashMap<String, (LeagueItem, RankTierEnum)> = master_result
-        .entries
-        .into_iter()
-        .map(|entry| (entry.summoner_id.clone(), (entry, RankTierEnum::Master)))
-        .chain(grandmaster_result.entries.into_iter().map(|entry| {
-            (
-                entry.summoner_id.clone(),
-                (entry, RankTierEnum::Grandmaster),
-            )
-        }))
-        .chain(
-            challenger_result
-                .entries
-                .into_iter()
-                .map(|entry| (entry.summoner_id.clone(), (entry, RankTierEnum::Challenger))),
-        )
-        .collect();
+    let result = merge_apex_tiers([
+        (master_result.entries, RankTierEnum::Master),
+        (grandmaster_result.entries, RankTierEnum::Grandmaster),
+        (challenger_result.entries, RankTierEnum::Challenger),
+    ]);
 
     info!(
         perf = t2.elapsed().as_millis(),
@@ -143,7 +172,7 @@ pub async fn upsert_players(
         })
         .collect();
 
-    for chunk in player_models.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in player_models.chunks(config::get().insert_chunk_size) {
         apex_tier_players::Entity::insert_many(chunk.to_vec())
             .on_conflict(
                 OnConflict::columns([
@@ -172,3 +201,111 @@ pub async fn upsert_players(
 
     Ok(())
 }
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::league_data_source::fake::FakeDataSource;
+    use crate::test_fixtures::league_item;
+
+    #[test]
+    fn merges_all_tiers() {
+        let players = merge_apex_tiers([
+            (vec![league_item("a", 100, 10, 5)], RankTierEnum::Master),
+            (
+                vec![league_item("b", 400, 10, 5)],
+                RankTierEnum::Grandmaster,
+            ),
+            (vec![league_item("c", 900, 10, 5)], RankTierEnum::Challenger),
+        ]);
+
+        assert_eq!(players.len(), 3);
+        assert_eq!(players["a"].1, RankTierEnum::Master);
+        assert_eq!(players["b"].1, RankTierEnum::Grandmaster);
+        assert_eq!(players["c"].1, RankTierEnum::Challenger);
+    }
+
+    #[test]
+    fn keeps_entry_after_dodge_when_player_is_in_two_tiers() {
+        // The player dodged below the Grandmaster threshold in between the league list requests
+        let players = merge_apex_tiers([
+            (vec![league_item("a", 195, 10, 5)], RankTierEnum::Master),
+            (
+                vec![league_item("a", 200, 10, 5)],
+                RankTierEnum::Grandmaster,
+            ),
+            (vec![], RankTierEnum::Challenger),
+        ]);
+
+        assert_eq!(players.len(), 1);
+        assert_eq!(players["a"].0.league_points, 195);
+        assert_eq!(players["a"].1, RankTierEnum::Master);
+    }
+
+    #[test]
+    fn keeps_entry_with_most_games_when_player_is_in_two_tiers() {
+        let players = merge_apex_tiers([
+            (vec![league_item("a", 180, 10, 6)], RankTierEnum::Master),
+            (
+                vec![league_item("a", 200, 10, 5)],
+                RankTierEnum::Grandmaster,
+            ),
+            (vec![], RankTierEnum::Challenger),
+        ]);
+
+        assert_eq!(players["a"].0.league_points, 180);
+        assert_eq!(players["a"].1, RankTierEnum::Master);
+
+        let players = merge_apex_tiers([
+            (vec![league_item("a", 200, 10, 5)], RankTierEnum::Master),
+            (
+                vec![league_item("a", 220, 11, 5)],
+                RankTierEnum::Grandmaster,
+            ),
+            (vec![], RankTierEnum::Challenger),
+        ]);
+
+        assert_eq!(players["a"].0.league_points, 220);
+        assert_eq!(players["a"].1, RankTierEnum::Grandmaster);
+    }
+
+    #[tokio::test]
+    async fn gets_players_from_data_source() {
+        let source = FakeDataSource::new()
+            .with_league(
+                PlatformRoute::EUW1,
+                RankTierEnum::Master,
+                vec![league_item("a", 100, 10, 5), league_item("b", 200, 10, 5)],
+            )
+            .with_league(
+                PlatformRoute::EUW1,
+                RankTierEnum::Grandmaster,
+                vec![league_item("c", 400, 10, 5)],
+            )
+            .with_league(PlatformRoute::EUW1, RankTierEnum::Challenger, vec![]);
+
+        let (players, counts) = get_players_from_api(&source, PlatformRoute::EUW1)
+            .await
+            .unwrap();
+
+        assert_eq!(players.len(), 3);
+        assert_eq!(players["c"].1, RankTierEnum::Grandmaster);
+        assert_eq!(counts, (2, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn fails_when_a_league_is_unavailable() {
+        let source = FakeDataSource::new().with_league(
+            PlatformRoute::EUW1,
+            RankTierEnum::Master,
+            vec![league_item("a", 100, 10, 5)],
+        );
+
+        assert!(get_players_from_api(&source, PlatformRoute::EUW1)
+            .await
+            .is_err());
+    }
+}
@@ -1 +1,953 @@
-pub const INSERT_CHUNK_SIZE: usize = 2000;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use dotenv::from_path;
+use riven::consts::PlatformRoute;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+use crate::dodge_detectors::DetectorKind;
+use crate::dodges::REPEAT_OFFENCE_LP_PENALTY;
+use crate::error::PipelineError;
+use crate::logger::LogRotation;
+use crate::lolpros::LOLPROS_SUPPORTED_REGIONS;
+use crate::rate_budget::{RateLimit, RateLimits};
+use crate::util::retry::RetryPolicy;
+
+/// Used if `DODGETRACKER_CONFIG` is not set. If this file doesn't exist either, the defaults are used.
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// The example config doubles as the default config, so the two can't drift apart.
+const DEFAULT_CONFIG: &str = include_str!("../config.example.toml");
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub regions: Vec<RegionConfig>,
+    pub insert_chunk_size: usize,
+    pub dodge_lp_ceiling: i64,
+    /// `None` if dodges masked by a game are not detected.
+    pub masked_dodges: Option<MaskedDodgesConfig>,
+    /// Dodges shortly after a season reset are less certain.
+    pub season_resets: Vec<DateTime<Utc>>,
+    pub lolpros_regions: Vec<PlatformRoute>,
+    pub timeouts: Timeouts,
+    /// Applied to every Riot and lolpros API query.
+    pub retry: RetryPolicy,
+    pub rate_limits: RateLimits,
+    pub ladder_recorder: Option<LadderRecorderConfig>,
+    pub status_server: Option<StatusServerConfig>,
+    pub otlp: Option<OtlpConfig>,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegionConfig {
+    pub region: PlatformRoute,
+    pub poll_interval: PollInterval,
+    pub detector: DetectorKind,
+}
+
+/// The minimum time between the start of two updates, adapted to the ladder activity by
+/// [`crate::polling::AdaptivePolling`]. Fixed if `min` and `max` are equal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PollInterval {
+    pub min: Duration,
+    pub max: Duration,
+    /// LP changes per update that the interval adapts towards.
+    pub target_lp_changes: f64,
+}
+
+/// The LP a single win gains or a single loss loses in the apex tiers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskedDodgesConfig {
+    pub min_game_lp: i64,
+    pub max_game_lp: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub league: Duration,
+    pub summoner: Duration,
+    pub account: Duration,
+    pub lolpros: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct LadderRecorderConfig {
+    pub dir: PathBuf,
+    /// Empty if every enabled region is recorded.
+    pub regions: Vec<PlatformRoute>,
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusServerConfig {
+    pub address: SocketAddr,
+    /// A region with this many failed updates in a row is not ready.
+    pub max_consecutive_failures: u32,
+    /// A region without a successful update for longer than this is not ready.
+    pub max_update_age: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct OtlpConfig {
+    /// gRPC endpoint of the collector, e.g. `http://localhost:4317`.
+    pub endpoint: String,
+    pub service_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggingConfig {
+    /// The text logs are written to `dir`, the JSON logs to `dir/json`.
+    pub dir: PathBuf,
+    /// `EnvFilter` directives for every sink, overridden by `RUST_LOG`.
+    pub filter: String,
+    pub stdout: bool,
+    pub journald: bool,
+    /// `None` if the sink is disabled.
+    pub text: Option<LogFileConfig>,
+    pub json: Option<LogFileConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    pub rotation: LogRotation,
+    /// Older files are deleted.
+    pub max_files: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    regions: BTreeMap<String, RawRegionConfig>,
+    insert_chunk_size: usize,
+    dodge_lp_ceiling: i64,
+    masked_dodges: Option<RawMaskedDodgesConfig>,
+    #[serde(default)]
+    season_resets: Vec<String>,
+    #[serde(default)]
+    lolpros_regions: Vec<String>,
+    timeouts: RawTimeouts,
+    retry: Option<RawRetryPolicy>,
+    rate_limits: Option<RawRateLimits>,
+    ladder_recorder: Option<RawLadderRecorderConfig>,
+    status_server: Option<RawStatusServerConfig>,
+    otlp: Option<RawOtlpConfig>,
+    #[serde(default)]
+    logging: RawLoggingConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRegionConfig {
+    poll_interval_ms: u64,
+    /// Defaults to `poll_interval_ms`, so the interval is fixed.
+    max_poll_interval_ms: Option<u64>,
+    #[serde(default = "default_target_lp_changes")]
+    target_lp_changes: f64,
+    detector: String,
+}
+
+fn default_target_lp_changes() -> f64 {
+    3.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMaskedDodgesConfig {
+    min_game_lp: i64,
+    max_game_lp: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTimeouts {
+    league_secs: u64,
+    summoner_secs: u64,
+    account_secs: u64,
+    lolpros_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRetryPolicy {
+    max_attempts: u32,
+    initial_backoff_ms: u64,
+    max_backoff_ms: u64,
+    jitter: f64,
+}
+
+/// Omitted fields keep the limits of a production API key.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRateLimits {
+    application: Option<Vec<RawRateLimit>>,
+    league: Option<Vec<RawRateLimit>>,
+    summoner: Option<Vec<RawRateLimit>>,
+    account: Option<Vec<RawRateLimit>>,
+    enrichment_share: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRateLimit {
+    requests: u32,
+    window_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLadderRecorderConfig {
+    dir: PathBuf,
+    #[serde(default)]
+    regions: Vec<String>,
+    max_files: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStatusServerConfig {
+    address: String,
+    max_consecutive_failures: u32,
+    max_update_age_secs: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOtlpConfig {
+    endpoint: String,
+    #[serde(default = "default_service_name")]
+    service_name: String,
+}
+
+fn default_service_name() -> String {
+    env!("CARGO_PKG_NAME").to_string()
+}
+
+/// Every field is optional, the defaults are the hourly text and daily JSON logs in `.log/`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawLoggingConfig {
+    dir: PathBuf,
+    filter: String,
+    stdout: bool,
+    journald: bool,
+    text: RawLogFileConfig,
+    json: RawLogFileConfig,
+}
+
+impl Default for RawLoggingConfig {
+    fn default() -> Self {
+        RawLoggingConfig {
+            dir: PathBuf::from(".log"),
+            filter: "info".to_string(),
+            stdout: false,
+            journald: false,
+            text: RawLogFileConfig::default(),
+            json: RawLogFileConfig::default(),
+        }
+    }
+}
+
+/// `rotation` and `max_files` default per sink, see [`parse_log_file`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RawLogFileConfig {
+    enabled: bool,
+    rotation: Option<String>,
+    max_files: Option<usize>,
+}
+
+impl Default for RawLogFileConfig {
+    fn default() -> Self {
+        RawLogFileConfig {
+            enabled: true,
+            rotation: None,
+            max_files: None,
+        }
+    }
+}
+
+/// Defaults to hourly text logs and daily JSON logs.
+fn parse_log_file(
+    raw: RawLogFileConfig,
+    field: &str,
+    errors: &mut Vec<String>,
+) -> Option<LogFileConfig> {
+    if !raw.enabled {
+        return None;
+    }
+
+    let (default_rotation, default_max_files) = match field {
+        "text" => ("hourly", 72), // three days worth of logs
+        _ => ("daily", 3),
+    };
+    let rotation = raw.rotation.as_deref().unwrap_or(default_rotation);
+    let max_files = raw.max_files.unwrap_or(default_max_files);
+
+    if max_files == 0 {
+        errors.push(format!("logging.{field}.max_files: must be greater than 0"));
+    }
+
+    match rotation.parse() {
+        Ok(rotation) => Some(LogFileConfig {
+            rotation,
+            max_files,
+        }),
+        Err(_) => {
+            errors.push(format!(
+                "logging.{field}.rotation: unknown rotation \"{rotation}\""
+            ));
+            None
+        }
+    }
+}
+
+fn parse_rate_limits(
+    raw: Option<Vec<RawRateLimit>>,
+    default: Vec<RateLimit>,
+    field: &str,
+    errors: &mut Vec<String>,
+) -> Vec<RateLimit> {
+    let Some(raw) = raw else {
+        return default;
+    };
+
+    raw.iter()
+        .map(|limit| {
+            if limit.requests == 0 || limit.window_secs == 0 {
+                errors.push(format!(
+                    "rate_limits.{field}: requests and window_secs must be greater than 0"
+                ));
+            }
+
+            RateLimit {
+                requests: limit.requests,
+                window: Duration::from_secs(limit.window_secs),
+            }
+        })
+        .collect()
+}
+
+fn parse_region(name: &str, field: &str, errors: &mut Vec<String>) -> Option<PlatformRoute> {
+    match name.parse() {
+        Ok(region) => Some(region),
+        Err(_) => {
+            errors.push(format!("{field}: unknown region \"{name}\""));
+            None
+        }
+    }
+}
+
+fn parse_timeout(secs: u64, field: &str, errors: &mut Vec<String>) -> Duration {
+    if secs == 0 {
+        errors.push(format!("timeouts.{field}: must be greater than 0"));
+    }
+    Duration::from_secs(secs)
+}
+
+impl TryFrom<RawConfig> for Config {
+    type Error = anyhow::Error;
+
+    /// Checks the whole config and reports every problem at once.
+    fn try_from(raw: RawConfig) -> Result<Self> {
+        let mut errors = vec![];
+
+        if raw.regions.is_empty() {
+            errors.push("regions: at least one region has to be enabled".to_string());
+        }
+
+        let regions: Vec<RegionConfig> = raw
+            .regions
+            .iter()
+            .filter_map(|(name, region_config)| {
+                let region = parse_region(name, "regions", &mut errors);
+                if region == Some(PlatformRoute::PBE1) {
+                    errors.push("regions: PBE1 has no ranked ladder".to_string());
+                }
+                let detector = match region_config.detector.parse() {
+                    Ok(detector) => Some(detector),
+                    Err(_) => {
+                        errors.push(format!(
+                            "regions.{name}.detector: unknown detector \"{}\"",
+                            region_config.detector
+                        ));
+                        None
+                    }
+                };
+                if region_config.poll_interval_ms == 0 {
+                    errors.push(format!(
+                        "regions.{name}.poll_interval_ms: must be greater than 0"
+                    ));
+                }
+                let max_poll_interval_ms = region_config
+                    .max_poll_interval_ms
+                    .unwrap_or(region_config.poll_interval_ms);
+                if max_poll_interval_ms < region_config.poll_interval_ms {
+                    errors.push(format!(
+                        "regions.{name}.max_poll_interval_ms: must not be less than poll_interval_ms"
+                    ));
+                }
+                if region_config.target_lp_changes.is_nan() || region_config.target_lp_changes <= 0.0 {
+                    errors.push(format!(
+                        "regions.{name}.target_lp_changes: must be greater than 0"
+                    ));
+                }
+
+                Some(RegionConfig {
+                    region: region?,
+                    poll_interval: PollInterval {
+                        min: Duration::from_millis(region_config.poll_interval_ms),
+                        max: Duration::from_millis(max_poll_interval_ms),
+                        target_lp_changes: region_config.target_lp_changes,
+                    },
+                    detector: detector?,
+                })
+            })
+            .collect();
+        let is_enabled = |region: &PlatformRoute| regions.iter().any(|r| r.region == *region);
+
+        if raw.insert_chunk_size == 0 {
+            errors.push("insert_chunk_size: must be greater than 0".to_string());
+        }
+
+        if raw.dodge_lp_ceiling < REPEAT_OFFENCE_LP_PENALTY {
+            errors.push(format!(
+                "dodge_lp_ceiling: must be at least the repeat offence penalty of {REPEAT_OFFENCE_LP_PENALTY} LP"
+            ));
+        }
+
+        let masked_dodges = raw.masked_dodges.map(|masked_dodges| {
+            if masked_dodges.min_game_lp <= 0 {
+                errors.push("masked_dodges.min_game_lp: must be greater than 0".to_string());
+            }
+            if masked_dodges.max_game_lp < masked_dodges.min_game_lp {
+                errors.push(
+                    "masked_dodges.max_game_lp: must not be less than min_game_lp".to_string(),
+                );
+            }
+
+            MaskedDodgesConfig {
+                min_game_lp: masked_dodges.min_game_lp,
+                max_game_lp: masked_dodges.max_game_lp,
+            }
+        });
+
+        let season_resets = raw
+            .season_resets
+            .iter()
+            .filter_map(|reset| match DateTime::parse_from_rfc3339(reset) {
+                Ok(reset) => Some(reset.with_timezone(&Utc)),
+                Err(_) => {
+                    errors.push(format!(
+                        "season_resets: \"{reset}\" is not an RFC 3339 time"
+                    ));
+                    None
+                }
+            })
+            .collect();
+
+        let mut lolpros_regions = vec![];
+        for name in &raw.lolpros_regions {
+            match parse_region(name, "lolpros_regions", &mut errors) {
+                Some(region) if !is_enabled(&region) => {
+                    errors.push(format!("lolpros_regions: region {name} is not enabled"))
+                }
+                Some(region) if !LOLPROS_SUPPORTED_REGIONS.contains(&region) => errors.push(
+                    format!("lolpros_regions: lolpros.gg does not list {name} players"),
+                ),
+                Some(region) => lolpros_regions.push(region),
+                None => {}
+            }
+        }
+
+        let timeouts = Timeouts {
+            league: parse_timeout(raw.timeouts.league_secs, "league_secs", &mut errors),
+            summoner: parse_timeout(raw.timeouts.summoner_secs, "summoner_secs", &mut errors),
+            account: parse_timeout(raw.timeouts.account_secs, "account_secs", &mut errors),
+            lolpros: parse_timeout(raw.timeouts.lolpros_secs, "lolpros_secs", &mut errors),
+        };
+
+        let retry = raw.retry.map_or_else(RetryPolicy::default, |retry| {
+            if retry.max_attempts == 0 {
+                errors.push("retry.max_attempts: must be greater than 0".to_string());
+            }
+            if retry.initial_backoff_ms > retry.max_backoff_ms {
+                errors.push(
+                    "retry.initial_backoff_ms: must not be greater than max_backoff_ms".to_string(),
+                );
+            }
+            if !(0.0..=1.0).contains(&retry.jitter) {
+                errors.push("retry.jitter: must be between 0 and 1".to_string());
+            }
+
+            RetryPolicy {
+                max_attempts: retry.max_attempts,
+                initial_backoff: Duration::from_millis(retry.initial_backoff_ms),
+                max_backoff: Duration::from_millis(retry.max_backoff_ms),
+                jitter: retry.jitter,
+            }
+        });
+
+        let rate_limits = raw.rate_limits.map_or_else(RateLimits::default, |limits| {
+            let default = RateLimits::default();
+
+            let enrichment_share = limits.enrichment_share.unwrap_or(default.enrichment_share);
+            if !(enrichment_share > 0.0 && enrichment_share <= 1.0) {
+                errors.push(
+                    "rate_limits.enrichment_share: must be greater than 0 and at most 1"
+                        .to_string(),
+                );
+            }
+
+            RateLimits {
+                application: parse_rate_limits(
+                    limits.application,
+                    default.application,
+                    "application",
+                    &mut errors,
+                ),
+                league: parse_rate_limits(limits.league, default.league, "league", &mut errors),
+                summoner: parse_rate_limits(
+                    limits.summoner,
+                    default.summoner,
+                    "summoner",
+                    &mut errors,
+                ),
+                account: parse_rate_limits(limits.account, default.account, "account", &mut errors),
+                enrichment_share,
+            }
+        });
+
+        let ladder_recorder = raw.ladder_recorder.map(|recorder| {
+            if recorder.max_files == 0 {
+                errors.push("ladder_recorder.max_files: must be greater than 0".to_string());
+            }
+
+            let mut recorder_regions = vec![];
+            for name in &recorder.regions {
+                match parse_region(name, "ladder_recorder.regions", &mut errors) {
+                    Some(region) if !is_enabled(&region) => errors.push(format!(
+                        "ladder_recorder.regions: region {name} is not enabled"
+                    )),
+                    Some(region) => recorder_regions.push(region),
+                    None => {}
+                }
+            }
+
+            LadderRecorderConfig {
+                dir: recorder.dir,
+                regions: recorder_regions,
+                max_files: recorder.max_files,
+            }
+        });
+
+        let status_server = raw.status_server.and_then(|server| {
+            if server.max_consecutive_failures == 0 {
+                errors.push(
+                    "status_server.max_consecutive_failures: must be greater than 0".to_string(),
+                );
+            }
+            if server.max_update_age_secs == 0 {
+                errors
+                    .push("status_server.max_update_age_secs: must be greater than 0".to_string());
+            }
+
+            match server.address.parse() {
+                Ok(address) => Some(StatusServerConfig {
+                    address,
+                    max_consecutive_failures: server.max_consecutive_failures,
+                    max_update_age: Duration::from_secs(server.max_update_age_secs),
+                }),
+                Err(_) => {
+                    errors.push(format!(
+                        "status_server.address: invalid socket address \"{}\"",
+                        server.address
+                    ));
+                    None
+                }
+            }
+        });
+
+        let otlp = raw.otlp.map(|otlp| {
+            if !otlp.endpoint.starts_with("http://") && !otlp.endpoint.starts_with("https://") {
+                errors.push(format!(
+                    "otlp.endpoint: \"{}\" is not an http:// or https:// URL",
+                    otlp.endpoint
+                ));
+            }
+
+            OtlpConfig {
+                endpoint: otlp.endpoint,
+                service_name: otlp.service_name,
+            }
+        });
+
+        let raw_logging = raw.logging;
+        if let Err(error) = EnvFilter::builder().parse(&raw_logging.filter) {
+            errors.push(format!("logging.filter: {error}"));
+        }
+        let logging = LoggingConfig {
+            dir: raw_logging.dir,
+            filter: raw_logging.filter,
+            stdout: raw_logging.stdout,
+            journald: raw_logging.journald,
+            text: parse_log_file(raw_logging.text, "text", &mut errors),
+            json: parse_log_file(raw_logging.json, "json", &mut errors),
+        };
+
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid config:\n  - {}", errors.join("\n  - ")));
+        }
+
+        Ok(Config {
+            regions,
+            insert_chunk_size: raw.insert_chunk_size,
+            dodge_lp_ceiling: raw.dodge_lp_ceiling,
+            masked_dodges,
+            season_resets,
+            lolpros_regions,
+            timeouts,
+            retry,
+            rate_limits,
+            ladder_recorder,
+            status_server,
+            otlp,
+            logging,
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::parse(DEFAULT_CONFIG).expect("Default config is invalid")
+    }
+}
+
+impl Config {
+    pub fn parse(toml: &str) -> Result<Self> {
+        let raw: RawConfig = toml::from_str(toml)?;
+        raw.try_into()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let toml = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Config::parse(&toml)
+            .with_context(|| format!("Failed to load config file {}", path.display()))
+    }
+
+    /// Loads the file at `DODGETRACKER_CONFIG`, or `config.toml` if it exists, or the defaults.
+    pub fn load() -> Result<Self, PipelineError> {
+        from_path("../../.env").ok();
+
+        match env::var("DODGETRACKER_CONFIG") {
+            Ok(path) => Config::from_file(Path::new(&path)),
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_PATH))
+            }
+            Err(_) => Ok(Config::default()),
+        }
+        .map_err(PipelineError::Config)
+    }
+}
+
+/// Sets the config returned by [`get`]. Has to be called before anything reads the config.
+pub fn init(config: Config) {
+    CONFIG
+        .set(config)
+        .expect("Config has already been initialized");
+}
+
+/// The config passed to [`init`], or the default config if it was never called (e.g. in tests).
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL_CONFIG: &str = r#"
+        insert_chunk_size = 500
+        dodge_lp_ceiling = 20
+
+        [timeouts]
+        league_secs = 10
+        summoner_secs = 10
+        account_secs = 5
+        lolpros_secs = 5
+
+        [regions.EUW1]
+        poll_interval_ms = 2000
+        detector = "strict"
+    "#;
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = Config::default();
+
+        assert_eq!(config.regions.len(), 5);
+        for region_config in &config.regions {
+            assert_eq!(region_config.poll_interval.min, Duration::from_millis(1300));
+            assert_eq!(region_config.poll_interval.max, Duration::from_millis(1300));
+        }
+        assert_eq!(config.insert_chunk_size, 2000);
+        assert_eq!(config.dodge_lp_ceiling, 15);
+        assert_eq!(config.lolpros_regions, vec![PlatformRoute::EUW1]);
+        assert!(config.masked_dodges.is_none());
+        assert!(config.ladder_recorder.is_none());
+    }
+
+    #[test]
+    fn parses_single_region_config() {
+        let config = Config::parse(MINIMAL_CONFIG).unwrap();
+
+        assert_eq!(config.regions.len(), 1);
+        assert_eq!(config.regions[0].region, PlatformRoute::EUW1);
+        assert_eq!(
+            config.regions[0].poll_interval.min,
+            Duration::from_millis(2000)
+        );
+        assert_eq!(
+            config.regions[0].poll_interval.max,
+            Duration::from_millis(2000)
+        );
+        assert_eq!(config.regions[0].detector, DetectorKind::Strict);
+        assert!(config.lolpros_regions.is_empty());
+        assert!(config.masked_dodges.is_none());
+        assert!(config.season_resets.is_empty());
+    }
+
+    #[test]
+    fn reports_every_validation_error() {
+        let toml = MINIMAL_CONFIG
+            .replace("dodge_lp_ceiling = 20", "dodge_lp_ceiling = 10")
+            .replace("detector = \"strict\"", "detector = \"unknown\"")
+            .replace("[regions.EUW1]", "[regions.EUW2]")
+            + r#"
+                [ladder_recorder]
+                dir = "archive"
+                regions = ["NA1"]
+                max_files = 0
+            "#;
+
+        let error = Config::parse(&toml).unwrap_err().to_string();
+
+        assert!(error.contains("dodge_lp_ceiling"), "{error}");
+        assert!(error.contains("unknown region \"EUW2\""), "{error}");
+        assert!(error.contains("unknown detector \"unknown\""), "{error}");
+        assert!(error.contains("ladder_recorder.max_files"), "{error}");
+        assert!(error.contains("region NA1 is not enabled"), "{error}");
+    }
+
+    #[test]
+    fn validates_status_server_address() {
+        let status_server = |address: &str| {
+            format!(
+                "{MINIMAL_CONFIG}\n[status_server]\naddress = \"{address}\"\nmax_consecutive_failures = 3\nmax_update_age_secs = 300\n"
+            )
+        };
+
+        let config = Config::parse(&status_server("127.0.0.1:9100")).unwrap();
+        assert_eq!(
+            config.status_server.unwrap().address,
+            "127.0.0.1:9100".parse().unwrap()
+        );
+
+        let error = Config::parse(&status_server("localhost"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("invalid socket address"), "{error}");
+    }
+
+    #[test]
+    fn validates_otlp_endpoint() {
+        let otlp =
+            |endpoint: &str| format!("{MINIMAL_CONFIG}\n[otlp]\nendpoint = \"{endpoint}\"\n");
+
+        let config = Config::parse(&otlp("http://localhost:4317")).unwrap();
+        let otlp_config = config.otlp.unwrap();
+        assert_eq!(otlp_config.endpoint, "http://localhost:4317");
+        assert_eq!(otlp_config.service_name, "dodgetracker-backend");
+
+        let error = Config::parse(&otlp("localhost:4317"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("otlp.endpoint"), "{error}");
+    }
+
+    #[test]
+    fn parses_logging_config() {
+        let config = Config::parse(MINIMAL_CONFIG).unwrap();
+        assert_eq!(config.logging.filter, "info");
+        assert_eq!(config.logging.text.unwrap().rotation, LogRotation::Hourly);
+        assert_eq!(config.logging.json.unwrap().max_files, 3);
+
+        let toml = format!(
+            "{MINIMAL_CONFIG}\n[logging]\nfilter = \"warn,dodgetracker_backend[cycle{{region=EUW1}}]=debug\"\n[logging.text]\nrotation = \"daily\"\nmax_files = 7\n[logging.json]\nenabled = false\n"
+        );
+        let config = Config::parse(&toml).unwrap();
+        assert_eq!(config.logging.text.unwrap().rotation, LogRotation::Daily);
+        assert!(config.logging.json.is_none());
+
+        let toml = format!(
+            "{MINIMAL_CONFIG}\n[logging.text]\nenabled = false\n[logging.json]\nmax_files = 7\n"
+        );
+        let config = Config::parse(&toml).unwrap();
+        assert!(config.logging.text.is_none());
+        let json = config.logging.json.unwrap();
+        assert_eq!(json.rotation, LogRotation::Daily);
+        assert_eq!(json.max_files, 7);
+
+        let toml = format!(
+            "{MINIMAL_CONFIG}\n[logging]\nfilter = \"info,[=debug\"\n[logging.text]\nrotation = \"weekly\"\nmax_files = 7\n"
+        );
+        let error = Config::parse(&toml).unwrap_err().to_string();
+        assert!(error.contains("logging.filter"), "{error}");
+        assert!(error.contains("unknown rotation \"weekly\""), "{error}");
+    }
+
+    #[test]
+    fn parses_retry_policy() {
+        let config = Config::parse(MINIMAL_CONFIG).unwrap();
+        assert_eq!(config.retry, RetryPolicy::default());
+
+        let retry = |jitter: &str| {
+            format!("{MINIMAL_CONFIG}\n[retry]\nmax_attempts = 5\ninitial_backoff_ms = 100\nmax_backoff_ms = 1000\njitter = {jitter}\n")
+        };
+        let config = Config::parse(&retry("0.2")).unwrap();
+        assert_eq!(config.retry.max_attempts, 5);
+        assert_eq!(config.retry.initial_backoff, Duration::from_millis(100));
+
+        let error = Config::parse(&retry("1.5")).unwrap_err().to_string();
+        assert!(error.contains("retry.jitter"), "{error}");
+    }
+
+    #[test]
+    fn parses_rate_limits() {
+        let config = Config::parse(MINIMAL_CONFIG).unwrap();
+        assert_eq!(config.rate_limits, RateLimits::default());
+
+        let rate_limits = |share: &str| {
+            format!("{MINIMAL_CONFIG}\n[rate_limits]\napplication = [{{ requests = 20, window_secs = 1 }}, {{ requests = 100, window_secs = 120 }}]\nenrichment_share = {share}\n")
+        };
+        let config = Config::parse(&rate_limits("0.5")).unwrap();
+        assert_eq!(config.rate_limits.application[1].requests, 100);
+        assert_eq!(
+            config.rate_limits.application[1].window,
+            Duration::from_secs(120)
+        );
+        assert_eq!(config.rate_limits.league, RateLimits::default().league);
+        assert_eq!(config.rate_limits.enrichment_share, 0.5);
+
+        let error = Config::parse(&rate_limits("0")).unwrap_err().to_string();
+        assert!(error.contains("rate_limits.enrichment_share"), "{error}");
+    }
+
+    #[test]
+    fn parses_adaptive_poll_interval() {
+        let toml = |max: u64| {
+            MINIMAL_CONFIG.replace(
+                "poll_interval_ms = 2000",
+                &format!(
+                    "poll_interval_ms = 2000\nmax_poll_interval_ms = {max}\ntarget_lp_changes = 5"
+                ),
+            )
+        };
+
+        let config = Config::parse(&toml(20000)).unwrap();
+        assert_eq!(
+            config.regions[0].poll_interval,
+            PollInterval {
+                min: Duration::from_millis(2000),
+                max: Duration::from_millis(20000),
+                target_lp_changes: 5.0,
+            }
+        );
+
+        let error = Config::parse(&toml(1000)).unwrap_err().to_string();
+        assert!(
+            error.contains("regions.EUW1.max_poll_interval_ms"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn parses_season_resets() {
+        let toml = format!("season_resets = [\"2026-01-08T12:00:00Z\"]\n{MINIMAL_CONFIG}");
+        let config = Config::parse(&toml).unwrap();
+        assert_eq!(
+            config.season_resets,
+            vec!["2026-01-08T12:00:00Z".parse::<DateTime<Utc>>().unwrap()]
+        );
+
+        let toml = format!("season_resets = [\"2026-01-08\"]\n{MINIMAL_CONFIG}");
+        let error = Config::parse(&toml).unwrap_err().to_string();
+        assert!(error.contains("not an RFC 3339 time"), "{error}");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let toml = MINIMAL_CONFIG.replace("insert_chunk_size", "insert_chunk");
+
+        assert!(Config::parse(&toml).is_err());
+    }
+
+    /// `MINIMAL_CONFIG` with its region replaced by `regions`.
+    fn config_with_regions(regions: &[&str]) -> String {
+        let (base, _) = MINIMAL_CONFIG.split_once("[regions.EUW1]").unwrap();
+
+        base.to_string()
+            + &regions
+                .iter()
+                .map(|region| {
+                    format!(
+                        "[regions.{region}]\npoll_interval_ms = 1300\ndetector = \"rule_based\"\n"
+                    )
+                })
+                .collect::<String>()
+    }
+
+    #[test]
+    fn accepts_every_ladder_region() {
+        let regions = [
+            "BR1", "EUN1", "EUW1", "JP1", "KR", "LA1", "LA2", "ME1", "NA1", "OC1", "PH2", "RU",
+            "SG2", "TH2", "TR1", "TW2", "VN2",
+        ];
+
+        let config = Config::parse(&config_with_regions(&regions)).unwrap();
+
+        assert_eq!(config.regions.len(), regions.len());
+    }
+
+    #[test]
+    fn rejects_regions_unsupported_by_lolpros_or_without_ladder() {
+        let error = Config::parse(&config_with_regions(&["PBE1"]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("PBE1 has no ranked ladder"), "{error}");
+
+        let toml = format!(
+            "lolpros_regions = [\"KR\"]\n{}",
+            config_with_regions(&["KR"])
+        );
+        let error = Config::parse(&toml).unwrap_err().to_string();
+        assert!(
+            error.contains("lolpros.gg does not list KR players"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Instant;
+
+use anyhow::Context;
+use chrono::Utc;
+use riven::consts::PlatformRoute;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use tracing::{error, info, instrument};
+
+use crate::config;
+use crate::dodge_detectors::{DodgeDetector, MaskedDodgeDetector};
+use crate::error::PipelineError;
+use crate::league_data_source::LeagueDataSource;
+use crate::{
+    apex_tier_players, decays, dodges, latest_updates, lolpros, lp_history, metrics, player_counts,
+    promotions_demotions, riot_ids, summoners,
+};
+
+/// What a successful update of a region found.
+#[derive(Debug, Clone, Copy)]
+pub struct CycleStats {
+    pub dodges: usize,
+    /// Players whose LP, wins or losses changed since the last update.
+    pub lp_changes: usize,
+}
+
+/// Runs one update of a region: fetches the ladder, detects and stores dodges, decays, LP history,
+/// promotions and demotions, and commits everything in a single transaction. Nothing is
+/// committed if a step fails.
+///
+/// Every update is its own root span, so it is exported as a separate trace.
+#[instrument(name = "cycle", parent = None, skip_all, fields(region = %region))]
+pub async fn run_cycle(
+    region: PlatformRoute,
+    source: &dyn LeagueDataSource,
+    detector: &dyn DodgeDetector,
+    db: &DatabaseConnection,
+) -> Result<CycleStats, PipelineError> {
+    let t1 = Instant::now();
+
+    info!("Starting transaction...");
+    let txn = db.begin().await.context("Failed to start transaction.")?;
+
+    let (api_players, (master_count, grandmaster_count, challenger_count)) =
+        apex_tier_players::get_players_from_api(source, region)
+            .await
+            .context("Error getting players from the League API.")?;
+
+    let db_players = apex_tier_players::get_players_from_db(&txn, region)
+        .await
+        .context("Error getting players from DB.")?;
+
+    let recent_history = dodges::RecentHistory {
+        dodges: dodges::get_recent_dodges(region, &txn)
+            .await
+            .context("Error getting recent dodges from DB.")?,
+        decays: decays::get_recent_decays(region, &txn)
+            .await
+            .context("Error getting recent decays from DB.")?,
+        ..dodges::RecentHistory::at(Utc::now())
+    };
+
+    let masked_detector = config::get()
+        .masked_dodges
+        .as_ref()
+        .map(MaskedDodgeDetector::new);
+    let dodges = dodges::find_dodges(
+        &db_players,
+        &api_players,
+        &recent_history,
+        detector,
+        masked_detector.as_ref(),
+    )
+    .await;
+
+    let decays = decays::find_decays(&db_players, &api_players, detector).await;
+
+    if !dodges.is_empty() {
+        let summoner_ids: Vec<&str> = dodges
+            .iter()
+            .filter_map(|dodge| match &dodge.summoner_id {
+                Set(id) => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        let riot_ids = summoners::upsert_summoners(source, &summoner_ids, region, &txn)
+            .await
+            .context("Error updating summoners table")?;
+
+        let riot_id_models = riot_ids::update_riot_ids(source, &riot_ids, region, &txn)
+            .await
+            .context("Error updating riot_ids table")?;
+
+        if config::get().lolpros_regions.contains(&region) {
+            match lolpros::upsert_lolpros_slugs(&riot_id_models, region, &txn).await {
+                Err(error @ PipelineError::LolPros(_)) => {
+                    error!(?error, "Error upserting Lolpros slugs. Ignoring.");
+                }
+                result => result?,
+            }
+        }
+
+        dodges::insert_dodges(&dodges, &txn)
+            .await
+            .context("Error inserting dodges")?;
+    }
+
+    decays::insert_decays(&decays, &txn)
+        .await
+        .context("Error inserting decays")?;
+
+    let lp_changes = lp_history::insert_lp_history(&api_players, &db_players, region, &txn)
+        .await
+        .context("Error inserting LP history")?;
+
+    apex_tier_players::upsert_players(&api_players, region, &txn)
+        .await
+        .context("Error upserting players")?;
+
+    let promotions =
+        promotions_demotions::insert_promotions(&api_players, &db_players, region, &txn)
+            .await
+            .context("Error inserting promotions")?;
+    let demotions = promotions_demotions::insert_demotions(&api_players, &db_players, region, &txn)
+        .await
+        .context("Error inserting demotions")?;
+
+    if let Err(error) = player_counts::update_player_counts(
+        master_count,
+        grandmaster_count,
+        challenger_count,
+        region,
+        &txn,
+    )
+    .await
+    {
+        error!(?error, "Error updating player counts. Ignoring.");
+    }
+
+    info!("Committing transaction...");
+    txn.commit()
+        .await
+        .context("Failed to commit transaction.")?;
+    metrics::record_cycle(region, dodges.len(), promotions, demotions);
+    info!(
+        perf = t1.elapsed().as_millis(),
+        metric = "region_update",
+        "Region update complete.",
+    );
+
+    if let Err(error) = latest_updates::set_latest_update(region, db).await {
+        error!(?error, "Error setting latest update time. Ignoring.");
+    }
+
+    Ok(CycleStats {
+        dodges: dodges.len(),
+        lp_changes,
+    })
+}
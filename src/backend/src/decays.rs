@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use anyhow::Result;
+use riven::consts::PlatformRoute;
+use riven::models::league_v4::LeagueItem;
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
+use tracing::{info, instrument};
+
+use crate::config;
+use crate::dodge_detectors::{DodgeDetector, Verdict, VerdictReason};
+use crate::entities::sea_orm_active_enums::RankTierEnum;
+use crate::entities::{apex_tier_players, decays};
+
+/// How long after a decay an LP loss of the same player could be decay as well.
+pub const RECENT_DECAY_WINDOW_HOURS: i64 = 72;
+
+#[instrument(skip_all)]
+pub async fn get_recent_decays(
+    region: PlatformRoute,
+    txn: &DatabaseTransaction,
+) -> Result<HashSet<String>> {
+    let t1 = Instant::now();
+
+    info!("Getting recent decays from DB...");
+
+    let window_start = chrono::Utc::now() - chrono::Duration::hours(RECENT_DECAY_WINDOW_HOURS);
+    let result: HashSet<String> = decays::Entity::find()
+        .filter(decays::Column::Region.eq(region.to_string()))
+        .filter(decays::Column::CreatedAt.gt(window_start))
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|decay| decay.summoner_id)
+        .collect();
+
+    info!(
+        perf = t1.elapsed().as_millis(),
+        players = result.len(),
+        metric = "recent_decays_db_query",
+        "Got recent decays from DB."
+    );
+
+    Ok(result)
+}
+
+/// Finds the LP losses that the detector rejected as dodges because they are too large to be a
+/// dodge penalty. Without a game played, these are caused by decay.
+#[instrument(skip_all, fields(db_players = db_players.len(), api_players = api_players.len()))]
+pub async fn find_decays(
+    db_players: &HashMap<String, apex_tier_players::Model>,
+    api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
+    detector: &dyn DodgeDetector,
+) -> Vec<decays::ActiveModel> {
+    let t1 = Instant::now();
+
+    info!("Finding decays...");
+
+    let decays: Vec<decays::ActiveModel> = api_players
+        .values()
+        .filter_map(|(new_data, _)| {
+            db_players.get(&new_data.summoner_id).and_then(|old_data| {
+                match detector.detect(old_data, new_data) {
+                    Verdict::NotDodge(VerdictReason::LpLossAboveCeiling) => {
+                        Some(decays::ActiveModel {
+                            summoner_id: ActiveValue::Set(old_data.summoner_id.clone()),
+                            region: ActiveValue::Set(old_data.region.clone()),
+                            lp_before: ActiveValue::Set(old_data.current_lp),
+                            lp_after: ActiveValue::Set(new_data.league_points as i64),
+                            rank_tier: ActiveValue::Set(old_data.rank_tier.clone()),
+                            at_wins: ActiveValue::Set(old_data.wins),
+                            at_losses: ActiveValue::Set(old_data.losses),
+                            ..Default::default()
+                        })
+                    }
+                    _ => None,
+                }
+            })
+        })
+        .collect();
+
+    info!(
+        perf = t1.elapsed().as_millis(),
+        decays = decays.len(),
+        metric = "decay_detection",
+        "Found decays."
+    );
+
+    decays
+}
+
+#[instrument(skip_all, fields(decays = decays.len()))]
+pub async fn insert_decays(
+    decays: &[decays::ActiveModel],
+    txn: &DatabaseTransaction,
+) -> Result<()> {
+    if decays.is_empty() {
+        return Ok(());
+    }
+
+    let t1 = Instant::now();
+    info!("Inserting decays...");
+
+    for chunk in decays.chunks(config::get().insert_chunk_size) {
+        decays::Entity::insert_many(chunk.to_vec())
+            .exec(txn)
+            .await?;
+    }
+
+    info!(
+        perf = t1.elapsed().as_millis(),
+        decays = decays.len(),
+        metric = "decays_inserted",
+        "Inserted decays into DB."
+    );
+
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::dodge_detectors::DetectorKind;
+    use crate::test_fixtures::{db_player, league_item};
+
+    fn players(
+        old_lp: i64,
+        new_lp: i32,
+    ) -> (
+        HashMap<String, apex_tier_players::Model>,
+        HashMap<String, (LeagueItem, RankTierEnum)>,
+    ) {
+        let db_players = HashMap::from([(
+            "summoner1".to_string(),
+            db_player("summoner1", old_lp, 10, 5),
+        )]);
+
+        let api_players = HashMap::from([(
+            "summoner1".to_string(),
+            (
+                LeagueItem {
+                    inactive: true,
+                    ..league_item("summoner1", new_lp, 10, 5)
+                },
+                RankTierEnum::Master,
+            ),
+        )]);
+
+        (db_players, api_players)
+    }
+
+    #[tokio::test]
+    async fn finds_decay() {
+        let (db_players, api_players) = players(200, 125);
+
+        for kind in DetectorKind::iter() {
+            let decays = find_decays(&db_players, &api_players, kind.detector().as_ref()).await;
+            assert_eq!(decays.len(), 1, "detector: {kind}");
+            assert_eq!(decays[0].lp_before.as_ref(), &200);
+            assert_eq!(decays[0].lp_after.as_ref(), &125);
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_find_decay_for_dodge() {
+        let (db_players, api_players) = players(100, 95);
+
+        for kind in DetectorKind::iter() {
+            let decays = find_decays(&db_players, &api_players, kind.detector().as_ref()).await;
+            assert_eq!(decays.len(), 0, "detector: {kind}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use chrono::{DateTime, Duration, Utc};
+use strum_macros::Display;
+
+use crate::entities::sea_orm_active_enums::DodgeTypeEnum;
+
+/// A player whose stored ladder entry is older than this could have done more in between than the
+/// LP change shows, e.g. after the region was paused.
+const STALE_UPDATE_SECS: i64 = 5 * 60;
+
+/// How long the ladder keeps settling after a season reset.
+const SEASON_RESET_WINDOW_HOURS: i64 = 72;
+
+/// Why a dodge is more or less certain. Stored with every dodge, so borderline detections can be
+/// filtered out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ConfidenceReason {
+    /// The LP lost matches a dodge penalty exactly.
+    MatchesPenalty,
+    /// The LP lost ends at 0 LP, where every penalty is clamped.
+    ClampedAtZeroLp,
+    /// The LP lost does not match any dodge penalty.
+    NoMatchingPenalty,
+    /// The player played a game since the last update, the penalty is inferred from its LP.
+    MaskedByGame,
+    /// The last update of the player is older than [`STALE_UPDATE_SECS`].
+    StaleUpdate,
+    /// The player is in a higher tier than at the last update, which losing LP can not explain.
+    TierRose,
+    /// The player decayed recently, so the LP loss could be decay as well.
+    RecentDecay,
+    /// The dodge is within [`SEASON_RESET_WINDOW_HOURS`] after a season reset.
+    NearSeasonReset,
+}
+
+impl ConfidenceReason {
+    /// How much the reason scales the confidence.
+    fn factor(self) -> f64 {
+        match self {
+            ConfidenceReason::MatchesPenalty => 1.0,
+            ConfidenceReason::ClampedAtZeroLp => 0.9,
+            ConfidenceReason::NoMatchingPenalty => 0.6,
+            ConfidenceReason::MaskedByGame => 0.6,
+            ConfidenceReason::StaleUpdate => 0.8,
+            ConfidenceReason::TierRose => 0.9,
+            ConfidenceReason::RecentDecay => 0.7,
+            ConfidenceReason::NearSeasonReset => 0.7,
+        }
+    }
+}
+
+/// What is known about a dodge besides the LP change.
+#[derive(Debug, Clone)]
+pub struct DodgeEvidence {
+    pub dodge_type: DodgeTypeEnum,
+    pub masked_by_game: bool,
+    pub since_last_update: Duration,
+    pub tier_rose: bool,
+    pub recently_decayed: bool,
+    pub near_season_reset: bool,
+}
+
+impl DodgeEvidence {
+    pub fn reasons(&self) -> Vec<ConfidenceReason> {
+        let mut reasons = vec![match self.dodge_type {
+            DodgeTypeEnum::FirstOffence | DodgeTypeEnum::RepeatOffence => {
+                ConfidenceReason::MatchesPenalty
+            }
+            DodgeTypeEnum::ZeroLpFloor => ConfidenceReason::ClampedAtZeroLp,
+            DodgeTypeEnum::Unknown => ConfidenceReason::NoMatchingPenalty,
+        }];
+
+        for (applies, reason) in [
+            (self.masked_by_game, ConfidenceReason::MaskedByGame),
+            (
+                self.since_last_update > Duration::seconds(STALE_UPDATE_SECS),
+                ConfidenceReason::StaleUpdate,
+            ),
+            (self.tier_rose, ConfidenceReason::TierRose),
+            (self.recently_decayed, ConfidenceReason::RecentDecay),
+            (self.near_season_reset, ConfidenceReason::NearSeasonReset),
+        ] {
+            if applies {
+                reasons.push(reason);
+            }
+        }
+
+        reasons
+    }
+}
+
+/// Between 0 and 1, 1 for a dodge that matches a penalty without anything that could explain it
+/// otherwise.
+pub fn confidence(reasons: &[ConfidenceReason]) -> f64 {
+    reasons.iter().map(|reason| reason.factor()).product()
+}
+
+pub fn is_near_season_reset(now: DateTime<Utc>, season_resets: &[DateTime<Utc>]) -> bool {
+    season_resets
+        .iter()
+        .any(|&reset| now >= reset && now - reset < Duration::hours(SEASON_RESET_WINDOW_HOURS))
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evidence(dodge_type: DodgeTypeEnum) -> DodgeEvidence {
+        DodgeEvidence {
+            dodge_type,
+            masked_by_game: false,
+            since_last_update: Duration::seconds(2),
+            tier_rose: false,
+            recently_decayed: false,
+            near_season_reset: false,
+        }
+    }
+
+    #[test]
+    fn trusts_exact_penalty_without_other_explanation() {
+        let reasons = evidence(DodgeTypeEnum::RepeatOffence).reasons();
+
+        assert_eq!(reasons, vec![ConfidenceReason::MatchesPenalty]);
+        assert_eq!(confidence(&reasons), 1.0);
+    }
+
+    #[test]
+    fn lowers_confidence_for_every_doubt() {
+        let reasons = DodgeEvidence {
+            masked_by_game: true,
+            since_last_update: Duration::minutes(10),
+            tier_rose: true,
+            recently_decayed: true,
+            ..evidence(DodgeTypeEnum::Unknown)
+        }
+        .reasons();
+
+        assert_eq!(
+            reasons,
+            vec![
+                ConfidenceReason::NoMatchingPenalty,
+                ConfidenceReason::MaskedByGame,
+                ConfidenceReason::StaleUpdate,
+                ConfidenceReason::TierRose,
+                ConfidenceReason::RecentDecay,
+            ]
+        );
+        assert!(confidence(&reasons) < confidence(&reasons[..4]));
+        assert!(confidence(&reasons) > 0.0);
+        assert_eq!(reasons[0].to_string(), "no_matching_penalty");
+    }
+
+    #[test]
+    fn detects_season_reset_proximity() {
+        let reset = "2026-01-08T12:00:00Z".parse().unwrap();
+
+        assert!(is_near_season_reset(reset + Duration::hours(1), &[reset]));
+        assert!(!is_near_season_reset(reset - Duration::hours(1), &[reset]));
+        assert!(!is_near_season_reset(reset + Duration::days(4), &[reset]));
+        assert!(!is_near_season_reset(reset, &[]));
+    }
+}
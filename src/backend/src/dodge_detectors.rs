@@ -0,0 +1,337 @@
+use riven::models::league_v4::LeagueItem;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::config::{self, MaskedDodgesConfig};
+use crate::dodges::{FIRST_OFFENCE_LP_PENALTY, REPEAT_OFFENCE_LP_PENALTY};
+use crate::entities::apex_tier_players;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum VerdictReason {
+    /// The player did not lose any LP.
+    NoLpLost,
+    /// The player played a game, so the LP change is explained by the game.
+    GamesPlayed,
+    /// The player lost more LP than a dodge can cost, most likely due to decay.
+    LpLossAboveCeiling,
+    /// The player lost at most the configured `dodge_lp_ceiling` LP without playing a game.
+    LpLossWithinCeiling,
+    /// The LP lost matches a known dodge penalty.
+    MatchesPenalty,
+    /// The LP lost ends at 0 LP and is smaller than the largest dodge penalty.
+    ClampedAtZeroLp,
+    /// The LP lost does not match any known dodge penalty.
+    NoMatchingPenalty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Dodge(VerdictReason),
+    NotDodge(VerdictReason),
+}
+
+/// Decides whether the change between a player's stored ladder entry and its latest ladder entry
+/// was caused by a dodge.
+pub trait DodgeDetector: Send + Sync {
+    fn detect(&self, old: &apex_tier_players::Model, new: &LeagueItem) -> Verdict;
+}
+
+/// Common checks for every detector: LP has to be lost without any games being played, and the
+/// loss can not be larger than the configured `dodge_lp_ceiling`, the maximum amount of LP a player
+/// can lose to a dodge. Larger losses are likely due to decay. Returns the LP lost if these checks
+/// pass.
+fn lp_lost_without_games(
+    old: &apex_tier_players::Model,
+    new: &LeagueItem,
+) -> Result<i64, VerdictReason> {
+    let lp_lost = old.current_lp - new.league_points as i64;
+
+    if lp_lost <= 0 {
+        Err(VerdictReason::NoLpLost)
+    } else if old.wins + old.losses != (new.wins + new.losses) as i64 {
+        Err(VerdictReason::GamesPlayed)
+    } else if lp_lost > config::get().dodge_lp_ceiling {
+        Err(VerdictReason::LpLossAboveCeiling)
+    } else {
+        Ok(lp_lost)
+    }
+}
+
+/// Treats every LP loss up to the configured `dodge_lp_ceiling` without a game played as a dodge.
+pub struct RuleBasedDetector;
+
+impl DodgeDetector for RuleBasedDetector {
+    fn detect(&self, old: &apex_tier_players::Model, new: &LeagueItem) -> Verdict {
+        match lp_lost_without_games(old, new) {
+            Ok(_) => Verdict::Dodge(VerdictReason::LpLossWithinCeiling),
+            Err(reason) => Verdict::NotDodge(reason),
+        }
+    }
+}
+
+/// Only accepts LP losses that are explained by a dodge penalty, either exactly or clamped by the
+/// 0 LP floor.
+pub struct StrictDetector;
+
+impl DodgeDetector for StrictDetector {
+    fn detect(&self, old: &apex_tier_players::Model, new: &LeagueItem) -> Verdict {
+        match lp_lost_without_games(old, new) {
+            Ok(FIRST_OFFENCE_LP_PENALTY | REPEAT_OFFENCE_LP_PENALTY) => {
+                Verdict::Dodge(VerdictReason::MatchesPenalty)
+            }
+            Ok(_) if new.league_points == 0 => Verdict::Dodge(VerdictReason::ClampedAtZeroLp),
+            Ok(_) => Verdict::NotDodge(VerdictReason::NoMatchingPenalty),
+            Err(reason) => Verdict::NotDodge(reason),
+        }
+    }
+}
+
+/// Finds dodges that the other detectors miss because the player finished a game before the next
+/// update. A single game changes the LP of a player by `min_game_lp` to `max_game_lp`, so a change
+/// outside of that range that a dodge penalty brings back into it is most likely a dodge followed by
+/// the game. Less certain than a dodge without a game, e.g. an unusually small win looks the same.
+pub struct MaskedDodgeDetector {
+    min_game_lp: i64,
+    max_game_lp: i64,
+}
+
+impl MaskedDodgeDetector {
+    pub fn new(config: &MaskedDodgesConfig) -> Self {
+        MaskedDodgeDetector {
+            min_game_lp: config.min_game_lp,
+            max_game_lp: config.max_game_lp,
+        }
+    }
+
+    /// The dodge penalties that explain the LP change together with the game. Empty unless exactly
+    /// one game was played and it can not explain the change on its own.
+    pub fn penalties(&self, old: &apex_tier_players::Model, new: &LeagueItem) -> Vec<i64> {
+        let new_lp = new.league_points as i64;
+
+        // The LP a win gains or a loss loses, the other direction is never plausible
+        let direction = match (new.wins as i64 - old.wins, new.losses as i64 - old.losses) {
+            (1, 0) => 1,
+            (0, 1) => -1,
+            _ => return vec![],
+        };
+        // Whether the game alone explains the change from `lp_before`. A loss that ends at 0 LP is
+        // clamped by the 0 LP floor, so it may have lost less than `min_game_lp`.
+        let is_game = |lp_before: i64| {
+            let lp_change = (new_lp - lp_before) * direction;
+            if direction < 0 && new_lp == 0 {
+                return lp_change <= self.max_game_lp;
+            }
+            (self.min_game_lp..=self.max_game_lp).contains(&lp_change)
+        };
+
+        if is_game(old.current_lp) {
+            return vec![];
+        }
+
+        // The penalty is clamped by the 0 LP floor as well, before the game is played
+        [FIRST_OFFENCE_LP_PENALTY, REPEAT_OFFENCE_LP_PENALTY]
+            .into_iter()
+            .filter(|&penalty| is_game(old.current_lp - penalty.min(old.current_lp)))
+            .collect()
+    }
+}
+
+/// Every registered detector, selectable per region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum DetectorKind {
+    RuleBased,
+    Strict,
+}
+
+impl DetectorKind {
+    pub fn detector(&self) -> Box<dyn DodgeDetector> {
+        match self {
+            DetectorKind::RuleBased => Box::new(RuleBasedDetector),
+            DetectorKind::Strict => Box::new(StrictDetector),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::test_fixtures::{db_player, league_item};
+
+    #[test]
+    fn rule_based_accepts_any_loss_within_ceiling() {
+        assert_eq!(
+            RuleBasedDetector.detect(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 90, 10, 5)
+            ),
+            Verdict::Dodge(VerdictReason::LpLossWithinCeiling)
+        );
+    }
+
+    #[test]
+    fn strict_rejects_loss_without_matching_penalty() {
+        assert_eq!(
+            StrictDetector.detect(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 90, 10, 5)
+            ),
+            Verdict::NotDodge(VerdictReason::NoMatchingPenalty)
+        );
+    }
+
+    #[test]
+    fn strict_accepts_loss_clamped_at_zero_lp() {
+        assert_eq!(
+            StrictDetector.detect(
+                &db_player("summoner1", 3, 10, 5),
+                &league_item("summoner1", 0, 10, 5)
+            ),
+            Verdict::Dodge(VerdictReason::ClampedAtZeroLp)
+        );
+    }
+
+    #[test]
+    fn reports_why_change_is_not_a_dodge() {
+        for kind in DetectorKind::iter() {
+            let detector = kind.detector();
+            assert_eq!(
+                detector.detect(
+                    &db_player("summoner1", 100, 10, 5),
+                    &league_item("summoner1", 100, 10, 5)
+                ),
+                Verdict::NotDodge(VerdictReason::NoLpLost)
+            );
+            assert_eq!(
+                detector.detect(
+                    &db_player("summoner1", 100, 10, 5),
+                    &league_item("summoner1", 95, 10, 6)
+                ),
+                Verdict::NotDodge(VerdictReason::GamesPlayed)
+            );
+            assert_eq!(
+                detector.detect(
+                    &db_player("summoner1", 200, 10, 5),
+                    &league_item("summoner1", 50, 10, 5)
+                ),
+                Verdict::NotDodge(VerdictReason::LpLossAboveCeiling)
+            );
+        }
+    }
+
+    fn masked_detector() -> MaskedDodgeDetector {
+        MaskedDodgeDetector::new(&MaskedDodgesConfig {
+            min_game_lp: 8,
+            max_game_lp: 40,
+        })
+    }
+
+    #[test]
+    fn detects_dodge_masked_by_game() {
+        let detector = masked_detector();
+
+        // A win of 17 LP after a 15 LP dodge
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 102, 11, 5)
+            ),
+            vec![REPEAT_OFFENCE_LP_PENALTY]
+        );
+        // A loss of 38 LP after a 15 LP dodge
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 47, 10, 6)
+            ),
+            vec![REPEAT_OFFENCE_LP_PENALTY]
+        );
+        // A win of 5 LP is too small, but either penalty explains it
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 105, 11, 5)
+            ),
+            vec![FIRST_OFFENCE_LP_PENALTY, REPEAT_OFFENCE_LP_PENALTY]
+        );
+        // A win can not lose LP without a dodge
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 100, 10, 5),
+                &league_item("summoner1", 98, 11, 5)
+            ),
+            vec![REPEAT_OFFENCE_LP_PENALTY]
+        );
+    }
+
+    #[test]
+    fn clamps_masked_dodge_penalty_at_zero_lp() {
+        let detector = masked_detector();
+
+        // A loss from 40 LP or less ends at 0 LP, so it can't lose 45 LP
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 45, 10, 5),
+                &league_item("summoner1", 0, 10, 6)
+            ),
+            vec![FIRST_OFFENCE_LP_PENALTY, REPEAT_OFFENCE_LP_PENALTY]
+        );
+        // Either penalty only drops 2 LP to 0 LP, and a win of 5 LP is too small
+        assert!(detector
+            .penalties(
+                &db_player("summoner1", 2, 10, 5),
+                &league_item("summoner1", 5, 11, 5)
+            )
+            .is_empty());
+        // The 15 LP penalty only drops 10 LP to 0 LP before a win of 9 LP
+        assert_eq!(
+            detector.penalties(
+                &db_player("summoner1", 10, 10, 5),
+                &league_item("summoner1", 9, 11, 5)
+            ),
+            vec![REPEAT_OFFENCE_LP_PENALTY]
+        );
+    }
+
+    #[test]
+    fn masked_detector_accepts_plausible_games() {
+        let detector = masked_detector();
+
+        for (old, new) in [
+            (
+                db_player("summoner1", 100, 10, 5),
+                league_item("summoner1", 120, 11, 5),
+            ),
+            (
+                db_player("summoner1", 100, 10, 5),
+                league_item("summoner1", 80, 10, 6),
+            ),
+            // Clamped by the 0 LP floor
+            (
+                db_player("summoner1", 5, 10, 5),
+                league_item("summoner1", 0, 10, 6),
+            ),
+            // Two games can explain anything
+            (
+                db_player("summoner1", 100, 10, 5),
+                league_item("summoner1", 80, 11, 6),
+            ),
+            (
+                db_player("summoner1", 100, 10, 5),
+                league_item("summoner1", 95, 10, 5),
+            ),
+        ] {
+            assert!(detector.penalties(&old, &new).is_empty(), "{new:?}");
+        }
+    }
+
+    #[test]
+    fn parses_detector_kind() {
+        assert_eq!("rule_based".parse(), Ok(DetectorKind::RuleBased));
+        assert_eq!("strict".parse(), Ok(DetectorKind::Strict));
+    }
+}
@@ -1,22 +1,183 @@
-use std::{collections::HashMap, time::Instant};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use riven::consts::PlatformRoute;
 use riven::models::league_v4::LeagueItem;
-use sea_orm::{ActiveValue, DatabaseTransaction, EntityTrait};
-use tracing::info;
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter};
 use tracing::instrument;
+use tracing::{debug, info};
 
-use crate::entities::sea_orm_active_enums::RankTierEnum;
+use crate::config;
+use crate::dodge_confidence::{self, DodgeEvidence};
+use crate::dodge_detectors::{DodgeDetector, MaskedDodgeDetector, Verdict, VerdictReason};
+use crate::entities::sea_orm_active_enums::{DodgeTypeEnum, RankTierEnum};
 use crate::entities::{apex_tier_players, dodges};
 
-/// The maximum amount of LP a player can lose without playing a game and still be considered a dodge.
-/// If a player loses more LP than this, it's likely due to decay.
-const DODGE_LP_CEILING: i32 = 15;
+/// LP penalty for a dodge when the player has not dodged within [`DODGE_PENALTY_WINDOW_HOURS`].
+pub const FIRST_OFFENCE_LP_PENALTY: i64 = 5;
+
+/// LP penalty for a dodge when the player has already dodged within [`DODGE_PENALTY_WINDOW_HOURS`].
+pub const REPEAT_OFFENCE_LP_PENALTY: i64 = 15;
+
+/// How long a dodge counts towards the escalated dodge penalty.
+pub const DODGE_PENALTY_WINDOW_HOURS: i64 = 24;
+
+/// Classifies a dodge by the penalty tier that explains the LP lost. A player can not go below 0 LP,
+/// so a smaller loss that ends at exactly 0 LP is a penalty that was clamped by the LP floor.
+fn classify_dodge(lp_lost: i64, lp_after: i64, has_recent_dodge: bool) -> DodgeTypeEnum {
+    let expected_penalty = if has_recent_dodge {
+        REPEAT_OFFENCE_LP_PENALTY
+    } else {
+        FIRST_OFFENCE_LP_PENALTY
+    };
+
+    if lp_lost == expected_penalty {
+        return if has_recent_dodge {
+            DodgeTypeEnum::RepeatOffence
+        } else {
+            DodgeTypeEnum::FirstOffence
+        };
+    }
+
+    if lp_after == 0 && lp_lost < expected_penalty {
+        return DodgeTypeEnum::ZeroLpFloor;
+    }
+
+    // The dodge history might be incomplete, so an exact penalty match is trusted over it
+    match lp_lost {
+        FIRST_OFFENCE_LP_PENALTY => DodgeTypeEnum::FirstOffence,
+        REPEAT_OFFENCE_LP_PENALTY => DodgeTypeEnum::RepeatOffence,
+        _ => DodgeTypeEnum::Unknown,
+    }
+}
+
+/// The penalty of a dodge masked by a game, preferring the one the dodge history expects if both
+/// explain the LP change.
+fn masked_penalty(penalties: &[i64], has_recent_dodge: bool) -> Option<i64> {
+    let expected_penalty = if has_recent_dodge {
+        REPEAT_OFFENCE_LP_PENALTY
+    } else {
+        FIRST_OFFENCE_LP_PENALTY
+    };
+
+    if penalties.contains(&expected_penalty) {
+        Some(expected_penalty)
+    } else {
+        penalties.first().copied()
+    }
+}
+
+/// The recent dodges and decays of a region's players, as of the update at `now`.
+pub struct RecentHistory {
+    pub dodges: HashSet<String>,
+    pub decays: HashSet<String>,
+    pub now: DateTime<Utc>,
+}
+
+impl RecentHistory {
+    /// Without any recent dodges or decays.
+    pub fn at(now: DateTime<Utc>) -> Self {
+        RecentHistory {
+            dodges: HashSet::new(),
+            decays: HashSet::new(),
+            now,
+        }
+    }
+}
+
+/// Dropping below the LP threshold of a tier is part of losing LP, only a higher tier is suspicious.
+fn tier_order(tier: &RankTierEnum) -> u8 {
+    match tier {
+        RankTierEnum::Master => 0,
+        RankTierEnum::Grandmaster => 1,
+        RankTierEnum::Challenger => 2,
+    }
+}
+
+fn dodge_model(
+    old_data: &apex_tier_players::Model,
+    new_tier: &RankTierEnum,
+    lp_after: i64,
+    masked_by_game: bool,
+    history: &RecentHistory,
+) -> dodges::ActiveModel {
+    let dodge_type = classify_dodge(
+        old_data.current_lp - lp_after,
+        lp_after,
+        history.dodges.contains(&old_data.summoner_id),
+    );
+
+    let reasons = DodgeEvidence {
+        dodge_type: dodge_type.clone(),
+        masked_by_game,
+        since_last_update: history.now - old_data.updated_at.with_timezone(&Utc),
+        tier_rose: tier_order(new_tier) > tier_order(&old_data.rank_tier),
+        recently_decayed: history.decays.contains(&old_data.summoner_id),
+        near_season_reset: dodge_confidence::is_near_season_reset(
+            history.now,
+            &config::get().season_resets,
+        ),
+    }
+    .reasons();
+
+    dodges::ActiveModel {
+        summoner_id: ActiveValue::Set(old_data.summoner_id.clone()),
+        region: ActiveValue::Set(old_data.region.clone()),
+        lp_before: ActiveValue::Set(old_data.current_lp),
+        lp_after: ActiveValue::Set(lp_after),
+        // A dodge can drop a player below the LP threshold of its tier, the tier it dodged in is
+        // the one it had before the LP was lost
+        rank_tier: ActiveValue::Set(old_data.rank_tier.clone()),
+        at_wins: ActiveValue::Set(old_data.wins),
+        at_losses: ActiveValue::Set(old_data.losses),
+        dodge_type: ActiveValue::Set(dodge_type),
+        masked_by_game: ActiveValue::Set(masked_by_game),
+        confidence: ActiveValue::Set(dodge_confidence::confidence(&reasons)),
+        confidence_reasons: ActiveValue::Set(
+            reasons.iter().map(|reason| reason.to_string()).collect(),
+        ),
+        ..Default::default()
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn get_recent_dodges(
+    region: PlatformRoute,
+    txn: &DatabaseTransaction,
+) -> Result<HashSet<String>> {
+    let t1 = Instant::now();
+
+    info!("Getting recent dodges from DB...");
+
+    let window_start = chrono::Utc::now() - chrono::Duration::hours(DODGE_PENALTY_WINDOW_HOURS);
+    let result: HashSet<String> = dodges::Entity::find()
+        .filter(dodges::Column::Region.eq(region.to_string()))
+        .filter(dodges::Column::CreatedAt.gt(window_start))
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|dodge| dodge.summoner_id)
+        .collect();
+
+    info!(
+        perf = t1.elapsed().as_millis(),
+        players = result.len(),
+        metric = "recent_dodges_db_query",
+        "Got recent dodges from DB."
+    );
+
+    Ok(result)
+}
 
 #[instrument(skip_all, fields(db_players = db_players.len(), api_players = api_players.len()))]
 pub async fn find_dodges(
     db_players: &HashMap<String, apex_tier_players::Model>,
     api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
+    history: &RecentHistory,
+    detector: &dyn DodgeDetector,
+    masked_detector: Option<&MaskedDodgeDetector>,
 ) -> Vec<dodges::ActiveModel> {
     let t1 = Instant::now();
 
@@ -24,29 +185,31 @@ pub async fn find_dodges(
 
     let dodges: Vec<dodges::ActiveModel> = api_players
         .values()
-        .filter_map(|(new_data, rank_tier)| {
-            db_players.get(&new_data.summoner_id).and_then(|old_data| {
-                let old_games_played = old_data.wins + old_data.losses;
-                let new_games_played = new_data.wins + new_data.losses;
-
-                if new_data.league_points < old_data.current_lp as i32
-                    && new_games_played == old_games_played as i32
-                    && (old_data.current_lp as i32 - new_data.league_points) <= DODGE_LP_CEILING
-                {
-                    Some(dodges::ActiveModel {
-                        summoner_id: ActiveValue::Set(old_data.summoner_id.clone()),
-                        region: ActiveValue::Set(old_data.region.clone()),
-                        lp_before: ActiveValue::Set(old_data.current_lp),
-                        lp_after: ActiveValue::Set(new_data.league_points as i64),
-                        rank_tier: ActiveValue::Set(rank_tier.clone()),
-                        at_wins: ActiveValue::Set(old_data.wins),
-                        at_losses: ActiveValue::Set(old_data.losses),
-                        ..Default::default()
-                    })
-                } else {
-                    None
+        .filter_map(|(new_data, new_tier)| {
+            let old_data = db_players.get(&new_data.summoner_id)?;
+
+            match detector.detect(old_data, new_data) {
+                Verdict::Dodge(reason) => {
+                    debug!(summoner_id = old_data.summoner_id, %reason, "Dodge detected.");
+
+                    let lp_after = new_data.league_points as i64;
+                    Some(dodge_model(old_data, new_tier, lp_after, false, history))
+                }
+                Verdict::NotDodge(VerdictReason::GamesPlayed) => {
+                    let penalties = masked_detector?.penalties(old_data, new_data);
+                    let has_recent_dodge = history.dodges.contains(&old_data.summoner_id);
+                    let penalty = masked_penalty(&penalties, has_recent_dodge)?;
+                    debug!(
+                        summoner_id = old_data.summoner_id,
+                        penalty, "Dodge masked by a game detected."
+                    );
+
+                    // The LP right after the dodge was never seen, only the LP after the game
+                    let lp_after = (old_data.current_lp - penalty).max(0);
+                    Some(dodge_model(old_data, new_tier, lp_after, true, history))
                 }
-            })
+                Verdict::NotDodge(_) => None,
+            }
         })
         .collect();
 
@@ -91,12 +254,15 @@ pub async fn insert_dodges(
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     use chrono::Utc;
     use riven::{consts::Division, models::league_v4::LeagueItem};
+    use strum::IntoEnumIterator;
 
     use super::*;
+    use crate::config::MaskedDodgesConfig;
+    use crate::dodge_detectors::DetectorKind;
     use crate::entities::apex_tier_players;
 
     #[tokio::test]
@@ -142,10 +308,19 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 1);
-        let dodge = &dodges[0];
-        assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            let dodge = &dodges[0];
+            assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        }
     }
 
     #[tokio::test]
@@ -191,8 +366,17 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 0);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 0, "detector: {kind}");
+        }
     }
 
     #[tokio::test]
@@ -238,8 +422,17 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 0);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 0, "detector: {kind}");
+        }
     }
 
     #[tokio::test]
@@ -285,8 +478,17 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 0);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 0, "detector: {kind}");
+        }
     }
 
     #[tokio::test]
@@ -332,10 +534,19 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 1);
-        let dodge = &dodges[0];
-        assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            let dodge = &dodges[0];
+            assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        }
     }
 
     #[tokio::test]
@@ -381,9 +592,248 @@ mod tests {
             ),
         );
 
-        let dodges = find_dodges(&db_players, &api_players).await;
-        assert_eq!(dodges.len(), 1);
-        let dodge = &dodges[0];
-        assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            let dodge = &dodges[0];
+            assert_eq!(dodge.summoner_id.as_ref(), &summoner_id_a);
+        }
+    }
+
+    #[test]
+    fn classifies_first_offence() {
+        assert_eq!(classify_dodge(5, 95, false), DodgeTypeEnum::FirstOffence);
+    }
+
+    #[test]
+    fn classifies_repeat_offence() {
+        assert_eq!(classify_dodge(15, 85, true), DodgeTypeEnum::RepeatOffence);
+    }
+
+    #[test]
+    fn trusts_lp_penalty_over_missing_history() {
+        // No recent dodge is known, but only a repeat offence costs 15 LP
+        assert_eq!(classify_dodge(15, 85, false), DodgeTypeEnum::RepeatOffence);
+    }
+
+    #[test]
+    fn classifies_zero_lp_floor() {
+        assert_eq!(classify_dodge(3, 0, false), DodgeTypeEnum::ZeroLpFloor);
+        assert_eq!(classify_dodge(12, 0, true), DodgeTypeEnum::ZeroLpFloor);
+    }
+
+    #[test]
+    fn classifies_unexplained_lp_loss_as_unknown() {
+        assert_eq!(classify_dodge(3, 50, false), DodgeTypeEnum::Unknown);
+        assert_eq!(classify_dodge(10, 90, true), DodgeTypeEnum::Unknown);
+    }
+
+    #[tokio::test]
+    async fn classifies_dodge_using_recent_dodges() {
+        let mut db_players = HashMap::new();
+        let mut api_players = HashMap::new();
+
+        let summoner_id_a = "summoner1".to_string();
+        let region = "EUW1".to_string();
+        let rank_tier = RankTierEnum::Challenger;
+
+        db_players.insert(
+            summoner_id_a.clone(),
+            apex_tier_players::Model {
+                summoner_id: summoner_id_a.clone(),
+                summoner_name: Some("summoner1".to_string()),
+                region: region.clone(),
+                current_lp: 10,
+                wins: 10,
+                losses: 5,
+                rank_tier: rank_tier.clone(),
+                created_at: Utc::now().into(),
+                updated_at: Utc::now().into(),
+            },
+        );
+
+        api_players.insert(
+            summoner_id_a.clone(),
+            (
+                LeagueItem {
+                    summoner_id: summoner_id_a.clone(),
+                    league_points: 0,
+                    wins: 10,
+                    losses: 5,
+                    fresh_blood: false,
+                    mini_series: None,
+                    inactive: false,
+                    veteran: false,
+                    hot_streak: false,
+                    rank: Division::I,
+                },
+                rank_tier.clone(),
+            ),
+        );
+
+        let recent_history = RecentHistory {
+            dodges: HashSet::from([summoner_id_a.clone()]),
+            ..RecentHistory::at(Utc::now())
+        };
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &recent_history,
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            assert_eq!(dodges[0].dodge_type.as_ref(), &DodgeTypeEnum::ZeroLpFloor);
+        }
+    }
+
+    #[tokio::test]
+    async fn detects_dodge_across_tier_boundary() {
+        let mut db_players = HashMap::new();
+        let mut api_players = HashMap::new();
+
+        let summoner_id_a = "summoner1".to_string();
+        let region = "EUW1".to_string();
+
+        db_players.insert(
+            summoner_id_a.clone(),
+            apex_tier_players::Model {
+                summoner_id: summoner_id_a.clone(),
+                summoner_name: Some("summoner1".to_string()),
+                region: region.clone(),
+                current_lp: 201,
+                wins: 10,
+                losses: 5,
+                rank_tier: RankTierEnum::Grandmaster,
+                created_at: Utc::now().into(),
+                updated_at: Utc::now().into(),
+            },
+        );
+
+        // The dodge dropped the player below the Grandmaster LP threshold, into Master
+        api_players.insert(
+            summoner_id_a.clone(),
+            (
+                LeagueItem {
+                    summoner_id: summoner_id_a.clone(),
+                    league_points: 196,
+                    wins: 10,
+                    losses: 5,
+                    fresh_blood: false,
+                    mini_series: None,
+                    inactive: false,
+                    veteran: false,
+                    hot_streak: false,
+                    rank: Division::I,
+                },
+                RankTierEnum::Master,
+            ),
+        );
+
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            let dodge = &dodges[0];
+            assert_eq!(dodge.lp_before.as_ref(), &201);
+            assert_eq!(dodge.lp_after.as_ref(), &196);
+            assert_eq!(dodge.rank_tier.as_ref(), &RankTierEnum::Grandmaster);
+            assert_eq!(dodge.confidence.as_ref(), &1.0);
+        }
+    }
+
+    #[test]
+    fn prefers_penalty_expected_by_dodge_history() {
+        let both = [FIRST_OFFENCE_LP_PENALTY, REPEAT_OFFENCE_LP_PENALTY];
+        assert_eq!(masked_penalty(&both, false), Some(FIRST_OFFENCE_LP_PENALTY));
+        assert_eq!(masked_penalty(&both, true), Some(REPEAT_OFFENCE_LP_PENALTY));
+        assert_eq!(
+            masked_penalty(&[REPEAT_OFFENCE_LP_PENALTY], false),
+            Some(REPEAT_OFFENCE_LP_PENALTY)
+        );
+        assert_eq!(masked_penalty(&[], true), None);
+    }
+
+    #[tokio::test]
+    async fn detects_dodge_masked_by_game() {
+        let summoner_id_a = "summoner1".to_string();
+
+        let db_players = HashMap::from([(
+            summoner_id_a.clone(),
+            apex_tier_players::Model {
+                summoner_id: summoner_id_a.clone(),
+                summoner_name: None,
+                region: "EUW1".to_string(),
+                current_lp: 100,
+                wins: 10,
+                losses: 5,
+                rank_tier: RankTierEnum::Challenger,
+                created_at: Utc::now().into(),
+                updated_at: Utc::now().into(),
+            },
+        )]);
+
+        // A win that loses LP, only possible after a 15 LP dodge
+        let api_players = HashMap::from([(
+            summoner_id_a.clone(),
+            (
+                LeagueItem {
+                    summoner_id: summoner_id_a.clone(),
+                    league_points: 95,
+                    wins: 11,
+                    losses: 5,
+                    fresh_blood: false,
+                    mini_series: None,
+                    inactive: false,
+                    veteran: false,
+                    hot_streak: false,
+                    rank: Division::I,
+                },
+                RankTierEnum::Challenger,
+            ),
+        )]);
+
+        let masked_detector = MaskedDodgeDetector::new(&MaskedDodgesConfig {
+            min_game_lp: 8,
+            max_game_lp: 40,
+        });
+
+        for kind in DetectorKind::iter() {
+            let dodges = find_dodges(
+                &db_players,
+                &api_players,
+                &RecentHistory::at(Utc::now()),
+                kind.detector().as_ref(),
+                Some(&masked_detector),
+            )
+            .await;
+            assert_eq!(dodges.len(), 1, "detector: {kind}");
+            let dodge = &dodges[0];
+            assert_eq!(dodge.masked_by_game.as_ref(), &true);
+            assert_eq!(dodge.lp_before.as_ref(), &100);
+            assert_eq!(dodge.lp_after.as_ref(), &85);
+            assert_eq!(dodge.at_wins.as_ref(), &10);
+            assert_eq!(dodge.dodge_type.as_ref(), &DodgeTypeEnum::RepeatOffence);
+            assert_eq!(dodge.confidence.as_ref(), &0.6);
+            assert_eq!(
+                dodge.confidence_reasons.as_ref(),
+                &vec!["matches_penalty".to_string(), "masked_by_game".to_string()]
+            );
+        }
     }
 }
@@ -2,9 +2,9 @@
 
 use sea_orm::entity::prelude::*;
 
-use super::sea_orm_active_enums::RankTierEnum;
+use super::sea_orm_active_enums::{DodgeTypeEnum, RankTierEnum};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(schema_name = "dodgetracker", table_name = "dodges")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -18,6 +18,11 @@ pub struct Model {
     pub at_wins: i64,
     pub at_losses: i64,
     pub rank_tier: RankTierEnum,
+    pub dodge_type: DodgeTypeEnum,
+    pub masked_by_game: bool,
+    #[sea_orm(column_type = "Double")]
+    pub confidence: f64,
+    pub confidence_reasons: Vec<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -3,10 +3,12 @@
 pub mod prelude;
 
 pub mod apex_tier_players;
+pub mod decays;
 pub mod demotions;
 pub mod dodges;
 pub mod latest_updates;
 pub mod lol_pros;
+pub mod lp_history;
 pub mod player_counts;
 pub mod promotions;
 pub mod riot_ids;
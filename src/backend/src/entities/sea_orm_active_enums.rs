@@ -2,6 +2,18 @@
 
 use sea_orm::entity::prelude::*;
 
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "dodge_type_enum")]
+pub enum DodgeTypeEnum {
+    #[sea_orm(string_value = "FIRST_OFFENCE")]
+    FirstOffence,
+    #[sea_orm(string_value = "REPEAT_OFFENCE")]
+    RepeatOffence,
+    #[sea_orm(string_value = "UNKNOWN")]
+    Unknown,
+    #[sea_orm(string_value = "ZERO_LP_FLOOR")]
+    ZeroLpFloor,
+}
 #[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
 #[sea_orm(rs_type = "String", db_type = "Enum", enum_name = "position_enum")]
 pub enum PositionEnum {
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use riven::RiotApiError;
+use sea_orm::DbErr;
+use thiserror::Error;
+
+use crate::util::TimeoutError;
+
+/// Wait before retrying a failed region update.
+const RETRY_WAIT: Duration = Duration::from_secs(5);
+/// Wait after the Riot API kept rate limiting the queries of an update, despite the retries.
+const RATE_LIMIT_WAIT: Duration = Duration::from_secs(30);
+/// How long every region is paused when the Riot API rejects the API key.
+const API_KEY_PAUSE: Duration = Duration::from_secs(10 * 60);
+
+/// Why a region update failed. Every variant keeps the whole error chain, including the context
+/// added by the pipeline steps, as its source.
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("{source:#}")]
+    RiotApi {
+        /// `None` if the query never got a response.
+        status: Option<u16>,
+        source: anyhow::Error,
+    },
+    #[error("{0:#}")]
+    Timeout(anyhow::Error),
+    #[error("{0:#}")]
+    Database(anyhow::Error),
+    #[error("{0:#}")]
+    LolPros(anyhow::Error),
+    #[error("{0:#}")]
+    Config(anyhow::Error),
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+/// How `run_region` reacts to a failed update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Retry the update of this region after the wait.
+    Retry(Duration),
+    /// Retrying can't succeed for any region, so all of them are paused.
+    PauseAllRegions(Duration),
+}
+
+impl PipelineError {
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            PipelineError::RiotApi {
+                status: Some(401 | 403),
+                ..
+            } => Recovery::PauseAllRegions(API_KEY_PAUSE),
+            PipelineError::RiotApi {
+                status: Some(429), ..
+            } => Recovery::Retry(RATE_LIMIT_WAIT),
+            // Only the lolpros slugs are lost, so the cycle ignores this unless a step propagates it
+            PipelineError::LolPros(_) => Recovery::Retry(RETRY_WAIT),
+            // `main` exits when the config fails to load at startup, before any region runs
+            PipelineError::Config(_) => Recovery::Retry(RETRY_WAIT),
+            _ => Recovery::Retry(RETRY_WAIT),
+        }
+    }
+}
+
+/// Classifies an error by the first error in its chain that the pipeline can react to. Errors of
+/// the lolpros and config steps have to be wrapped explicitly.
+impl From<anyhow::Error> for PipelineError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.chain().any(|cause| cause.is::<TimeoutError>()) {
+            return PipelineError::Timeout(error);
+        }
+
+        if let Some(riot_error) = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<RiotApiError>())
+        {
+            return PipelineError::RiotApi {
+                status: riot_error.status_code().map(|status| status.as_u16()),
+                source: error,
+            };
+        }
+
+        if error.chain().any(|cause| cause.is::<DbErr>()) {
+            return PipelineError::Database(error);
+        }
+
+        PipelineError::Other(error)
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+    use crate::util::with_timeout;
+
+    #[tokio::test]
+    async fn classifies_errors_through_context() {
+        let timeout = with_timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .context("Error getting players from the League API.")
+            .unwrap_err();
+        let error = PipelineError::from(timeout);
+        assert!(matches!(error, PipelineError::Timeout(_)));
+        assert!(error.to_string().starts_with("Error getting players"));
+        assert_eq!(error.recovery(), Recovery::Retry(RETRY_WAIT));
+
+        let db_error = anyhow::Error::from(DbErr::Custom("deadlock".to_string()))
+            .context("Error inserting dodges");
+        assert!(matches!(
+            PipelineError::from(db_error),
+            PipelineError::Database(_)
+        ));
+
+        assert!(matches!(
+            PipelineError::from(anyhow!("something else")),
+            PipelineError::Other(_)
+        ));
+    }
+
+    #[test]
+    fn pauses_all_regions_on_rejected_api_key() {
+        let error = |status| PipelineError::RiotApi {
+            status,
+            source: anyhow!("Riot API error"),
+        };
+
+        assert_eq!(
+            error(Some(403)).recovery(),
+            Recovery::PauseAllRegions(API_KEY_PAUSE)
+        );
+        assert_eq!(
+            error(Some(401)).recovery(),
+            Recovery::PauseAllRegions(API_KEY_PAUSE)
+        );
+        assert_eq!(
+            error(Some(429)).recovery(),
+            Recovery::Retry(RATE_LIMIT_WAIT)
+        );
+        assert_eq!(error(Some(503)).recovery(), Recovery::Retry(RETRY_WAIT));
+        assert_eq!(error(None).recovery(), Recovery::Retry(RETRY_WAIT));
+    }
+
+    #[test]
+    fn retries_lolpros_errors() {
+        let error = PipelineError::LolPros(anyhow!("lolpros.gg is unavailable"));
+        assert_eq!(error.recovery(), Recovery::Retry(RETRY_WAIT));
+    }
+
+    #[test]
+    fn retries_config_errors() {
+        let error = PipelineError::Config(anyhow!("unknown field `regoins`"));
+        assert_eq!(error.recovery(), Recovery::Retry(RETRY_WAIT));
+        assert_eq!(error.to_string(), "unknown field `regoins`");
+    }
+}
@@ -0,0 +1,226 @@
+//! End-to-end tests of a region cycle against a local Postgres database, using scripted ladder
+//! data instead of the Riot API. They are ignored by default, run them with
+//! `cargo test -- --include-ignored` and `TEST_DATABASE_URL` set. They drop and recreate the
+//! `dodgetracker` schema of that database, so never point it at real data.
+
+use std::env;
+
+use lazy_static::lazy_static;
+use riven::consts::PlatformRoute;
+use riven::models::league_v4::LeagueItem;
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, EntityTrait, PaginatorTrait,
+    Schema,
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::cycle::run_cycle;
+use crate::dodge_detectors::DetectorKind;
+use crate::entities::sea_orm_active_enums::{DodgeTypeEnum, PositionEnum, RankTierEnum};
+use crate::entities::{
+    apex_tier_players, decays, demotions, dodges, latest_updates, lol_pros, lp_history,
+    player_counts, promotions, riot_ids, summoners,
+};
+use crate::league_data_source::fake::FakeDataSource;
+use crate::test_fixtures::league_item;
+
+lazy_static! {
+    /// Every test recreates the same schema, so they can not run concurrently.
+    static ref DB_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// The entities only describe columns, the defaults of the drizzle schema have to be added
+/// separately.
+const COLUMN_DEFAULTS: &str = r#"
+DO $$
+DECLARE c record;
+BEGIN
+    FOR c IN
+        SELECT table_name, column_name FROM information_schema.columns
+        WHERE table_schema = 'dodgetracker'
+            AND column_name IN ('created_at', 'updated_at', 'at_time', 'update_time')
+    LOOP
+        EXECUTE format(
+            'ALTER TABLE dodgetracker.%I ALTER COLUMN %I SET DEFAULT now()',
+            c.table_name,
+            c.column_name
+        );
+    END LOOP;
+END $$;
+"#;
+
+async fn create_schema(db: &DatabaseConnection) {
+    db.execute_unprepared(
+        "DROP SCHEMA IF EXISTS dodgetracker CASCADE; CREATE SCHEMA dodgetracker;",
+    )
+    .await
+    .unwrap();
+
+    let backend = db.get_database_backend();
+    let schema = Schema::new(backend);
+
+    for statement in [
+        schema.create_enum_from_active_enum::<DodgeTypeEnum>(),
+        schema.create_enum_from_active_enum::<PositionEnum>(),
+        schema.create_enum_from_active_enum::<RankTierEnum>(),
+    ] {
+        db.execute(backend.build(&statement)).await.unwrap();
+    }
+
+    for statement in [
+        schema.create_table_from_entity(apex_tier_players::Entity),
+        schema.create_table_from_entity(decays::Entity),
+        schema.create_table_from_entity(demotions::Entity),
+        schema.create_table_from_entity(dodges::Entity),
+        schema.create_table_from_entity(latest_updates::Entity),
+        schema.create_table_from_entity(lol_pros::Entity),
+        schema.create_table_from_entity(lp_history::Entity),
+        schema.create_table_from_entity(player_counts::Entity),
+        schema.create_table_from_entity(promotions::Entity),
+        schema.create_table_from_entity(riot_ids::Entity),
+        schema.create_table_from_entity(summoners::Entity),
+    ] {
+        db.execute(backend.build(&statement)).await.unwrap();
+    }
+
+    db.execute_unprepared(COLUMN_DEFAULTS).await.unwrap();
+}
+
+/// Connects to the test database with a freshly created schema. The guard has to be held for the
+/// whole test.
+async fn test_db() -> (DatabaseConnection, MutexGuard<'static, ()>) {
+    let url = env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL must be set to run the integration tests");
+
+    let guard = DB_LOCK.lock().await;
+    let db = Database::connect(
+        ConnectOptions::new(url)
+            .sqlx_logging(false)
+            .set_schema_search_path("dodgetracker")
+            .to_owned(),
+    )
+    .await
+    .unwrap();
+    create_schema(&db).await;
+
+    (db, guard)
+}
+
+/// Scripts one cycle of ladder data for `region`.
+fn with_ladder(
+    source: FakeDataSource,
+    region: PlatformRoute,
+    master: Vec<LeagueItem>,
+    grandmaster: Vec<LeagueItem>,
+    challenger: Vec<LeagueItem>,
+) -> FakeDataSource {
+    source
+        .with_league(region, RankTierEnum::Master, master)
+        .with_league(region, RankTierEnum::Grandmaster, grandmaster)
+        .with_league(region, RankTierEnum::Challenger, challenger)
+}
+
+// EUW1 also queries lolpros.gg for dodgers, so the tests use a region without network access.
+const REGION: PlatformRoute = PlatformRoute::NA1;
+
+#[tokio::test]
+#[ignore = "needs a Postgres database in TEST_DATABASE_URL"]
+async fn cycle_records_dodges_promotions_and_demotions() {
+    let (db, _guard) = test_db().await;
+
+    let source = FakeDataSource::new()
+        .with_summoner(REGION, "a", "puuid-a")
+        .with_account("puuid-a", "Player A", "NA1");
+    let source = with_ladder(
+        source,
+        REGION,
+        vec![league_item("a", 100, 10, 5), league_item("b", 50, 20, 20)],
+        vec![league_item("d", 400, 30, 10)],
+        vec![],
+    );
+    let source = with_ladder(
+        source,
+        REGION,
+        vec![league_item("a", 95, 10, 5), league_item("c", 0, 5, 5)],
+        vec![league_item("d", 410, 31, 10)],
+        vec![],
+    );
+    let detector = DetectorKind::RuleBased.detector();
+
+    run_cycle(REGION, &source, detector.as_ref(), &db)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        apex_tier_players::Entity::find().count(&db).await.unwrap(),
+        3
+    );
+    assert_eq!(promotions::Entity::find().count(&db).await.unwrap(), 3);
+    assert_eq!(dodges::Entity::find().count(&db).await.unwrap(), 0);
+
+    run_cycle(REGION, &source, detector.as_ref(), &db)
+        .await
+        .unwrap();
+
+    let dodges = dodges::Entity::find().all(&db).await.unwrap();
+    assert_eq!(dodges.len(), 1);
+    assert_eq!(dodges[0].summoner_id, "a");
+    assert_eq!(dodges[0].region, "NA1");
+    assert_eq!((dodges[0].lp_before, dodges[0].lp_after), (100, 95));
+    assert_eq!(dodges[0].rank_tier, RankTierEnum::Master);
+    assert_eq!(dodges[0].dodge_type, DodgeTypeEnum::FirstOffence);
+    assert!(!dodges[0].masked_by_game);
+    assert_eq!(dodges[0].confidence, 1.0);
+    assert_eq!(dodges[0].confidence_reasons, vec!["matches_penalty"]);
+
+    let riot_ids = riot_ids::Entity::find().all(&db).await.unwrap();
+    assert_eq!(riot_ids.len(), 1);
+    assert_eq!(riot_ids[0].game_name, "Player A");
+
+    let promotions = promotions::Entity::find().all(&db).await.unwrap();
+    assert_eq!(promotions.len(), 4);
+    assert!(promotions.iter().any(|p| p.summoner_id == "c"));
+
+    let demotions = demotions::Entity::find().all(&db).await.unwrap();
+    assert_eq!(demotions.len(), 1);
+    assert_eq!(demotions[0].summoner_id, "b");
+    assert_eq!((demotions[0].at_wins, demotions[0].at_losses), (20, 20));
+
+    // Player counts are only stored once per hour
+    let player_counts = player_counts::Entity::find().all(&db).await.unwrap();
+    assert_eq!(player_counts.len(), 3);
+    assert!(player_counts
+        .iter()
+        .any(|c| c.rank_tier == RankTierEnum::Master && c.player_count == 2));
+
+    let latest_updates = latest_updates::Entity::find().all(&db).await.unwrap();
+    assert_eq!(latest_updates.len(), 1);
+    assert_eq!(latest_updates[0].region, "NA1");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database in TEST_DATABASE_URL"]
+async fn failed_cycle_commits_nothing() {
+    let (db, _guard) = test_db().await;
+
+    // The challenger league is missing, so fetching the ladder fails
+    let source = FakeDataSource::new()
+        .with_league(
+            REGION,
+            RankTierEnum::Master,
+            vec![league_item("a", 100, 10, 5)],
+        )
+        .with_league(REGION, RankTierEnum::Grandmaster, vec![]);
+    let detector = DetectorKind::RuleBased.detector();
+
+    assert!(run_cycle(REGION, &source, detector.as_ref(), &db)
+        .await
+        .is_err());
+
+    assert_eq!(
+        apex_tier_players::Entity::find().count(&db).await.unwrap(),
+        0
+    );
+    assert_eq!(promotions::Entity::find().count(&db).await.unwrap(), 0);
+    assert_eq!(latest_updates::Entity::find().count(&db).await.unwrap(), 0);
+}
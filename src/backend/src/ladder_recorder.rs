@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lazy_static::lazy_static;
+use riven::consts::PlatformRoute;
+use riven::models::league_v4::LeagueList;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, instrument};
+
+use crate::config;
+
+/// Extension of the archive files. Every archive holds one JSON line per recorded API poll, each
+/// line compressed as its own gzip member so records can be appended without rewriting the file.
+pub const ARCHIVE_EXTENSION: &str = "jsonl.gz";
+
+lazy_static! {
+    /// `None` unless the `ladder_recorder` section is configured.
+    pub static ref LADDER_RECORDER: Option<LadderRecorder> =
+        config::get().ladder_recorder.as_ref().map(|recorder| {
+            LadderRecorder::new(
+                recorder.dir.clone(),
+                recorder.regions.clone(),
+                recorder.max_files,
+            )
+        });
+}
+
+/// The raw apex tier league lists of one API poll.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LadderRecord<L = LeagueList> {
+    /// Unix timestamp in milliseconds.
+    pub timestamp: i64,
+    pub region: String,
+    pub master: L,
+    pub grandmaster: L,
+    pub challenger: L,
+}
+
+/// A snapshot waiting to be written by the writer of its region.
+type Snapshot = (DateTime<Utc>, Vec<u8>);
+
+/// Appends the snapshots of one region in the order they were recorded. Its thread stops once the
+/// sender is dropped and every queued snapshot is written.
+struct RegionWriter {
+    sender: mpsc::Sender<Snapshot>,
+    thread: JoinHandle<()>,
+}
+
+/// Writes the League API responses of the configured regions to hourly gzip archives in
+/// `<dir>/<REGION>/`, keeping at most `max_files` archives per region.
+pub struct LadderRecorder {
+    archives: Archives,
+    regions: Vec<PlatformRoute>,
+    /// One writer per region, so a slow write can't interleave with the next snapshot of the same
+    /// archive.
+    writers: Mutex<HashMap<PlatformRoute, RegionWriter>>,
+}
+
+impl LadderRecorder {
+    /// An empty `regions` records every region.
+    pub fn new(dir: PathBuf, regions: Vec<PlatformRoute>, max_files: usize) -> Self {
+        LadderRecorder {
+            archives: Archives { dir, max_files },
+            regions,
+            writers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self, region: PlatformRoute) -> bool {
+        self.regions.is_empty() || self.regions.contains(&region)
+    }
+
+    /// Serializes the league lists and queues them for the writer of the region. Recording is best
+    /// effort: failures are logged and never interrupt the region cycle.
+    #[instrument(skip_all)]
+    pub fn record(
+        &self,
+        region: PlatformRoute,
+        master: &LeagueList,
+        grandmaster: &LeagueList,
+        challenger: &LeagueList,
+    ) {
+        if !self.is_enabled(region) {
+            return;
+        }
+
+        let timestamp = Utc::now();
+        let line = match serde_json::to_vec(&LadderRecord {
+            timestamp: timestamp.timestamp_millis(),
+            region: region.to_string(),
+            master,
+            grandmaster,
+            challenger,
+        }) {
+            Ok(line) => line,
+            Err(error) => {
+                error!(?error, "Failed to serialize ladder record.");
+                return;
+            }
+        };
+
+        let mut writers = self.writers.lock().unwrap();
+        let writer = writers
+            .entry(region)
+            .or_insert_with(|| self.spawn_writer(region));
+        if writer.sender.send((timestamp, line)).is_err() {
+            error!(%region, "Failed to record ladder snapshot, the writer stopped.");
+        }
+    }
+
+    fn spawn_writer(&self, region: PlatformRoute) -> RegionWriter {
+        let (sender, receiver) = mpsc::channel::<Snapshot>();
+        let archives = self.archives.clone();
+
+        let thread = thread::spawn(move || {
+            for (timestamp, line) in receiver {
+                let t1 = Instant::now();
+
+                match archives.write(region, timestamp, &line) {
+                    Ok(()) => info!(
+                        perf = t1.elapsed().as_millis(),
+                        bytes = line.len(),
+                        %region,
+                        metric = "ladder_recorded",
+                        "Recorded ladder snapshot."
+                    ),
+                    Err(error) => error!(?error, %region, "Failed to record ladder snapshot."),
+                }
+            }
+        });
+
+        RegionWriter { sender, thread }
+    }
+
+    /// Waits until every recorded snapshot is written, e.g. before shutting down. Snapshots recorded
+    /// afterwards start new writers.
+    pub fn flush(&self) {
+        let writers = std::mem::take(&mut *self.writers.lock().unwrap());
+
+        for (region, RegionWriter { sender, thread }) in writers {
+            drop(sender);
+            if thread.join().is_err() {
+                error!(%region, "Ladder recorder writer panicked.");
+            }
+        }
+    }
+}
+
+/// The archive files of every region.
+#[derive(Debug, Clone)]
+struct Archives {
+    dir: PathBuf,
+    max_files: usize,
+}
+
+impl Archives {
+    fn write(&self, region: PlatformRoute, timestamp: DateTime<Utc>, line: &[u8]) -> Result<()> {
+        let region_dir = self.dir.join(region.to_string());
+        fs::create_dir_all(&region_dir)
+            .with_context(|| format!("Failed to create {}", region_dir.display()))?;
+
+        let path = region_dir.join(format!(
+            "{}.{}",
+            timestamp.format("%Y-%m-%d-%H"),
+            ARCHIVE_EXTENSION
+        ));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(line)?;
+        encoder.write_all(b"\n")?;
+        encoder.finish()?;
+
+        self.rotate(&region_dir)
+    }
+
+    /// Deletes the oldest archives of a region until at most `max_files` are left. The archive
+    /// names sort chronologically.
+    fn rotate(&self, region_dir: &Path) -> Result<()> {
+        let mut archives = archives_in(region_dir)?;
+
+        if archives.len() > self.max_files {
+            for path in archives.drain(..archives.len() - self.max_files) {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+                info!(path = %path.display(), "Removed old ladder archive.");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The archives in `dir`, oldest first.
+pub fn archives_in(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut archives = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(ARCHIVE_EXTENSION))
+        {
+            archives.push(path);
+        }
+    }
+
+    archives.sort();
+
+    Ok(archives)
+}
+
+pub fn read_archive(path: &Path) -> Result<Vec<LadderRecord>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    BufReader::new(MultiGzDecoder::new(file))
+        .lines()
+        .map(|line| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("Failed to parse record in {}", path.display()))
+        })
+        .collect()
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn league_list(tier: &str) -> LeagueList {
+        serde_json::from_str(&format!(
+            r#"{{"tier": "{tier}", "queue": "RANKED_SOLO_5x5", "entries": []}}"#
+        ))
+        .unwrap()
+    }
+
+    fn write_record(recorder: &LadderRecorder, timestamp: DateTime<Utc>) {
+        let line = serde_json::to_vec(&LadderRecord {
+            timestamp: timestamp.timestamp_millis(),
+            region: PlatformRoute::EUW1.to_string(),
+            master: &league_list("MASTER"),
+            grandmaster: &league_list("GRANDMASTER"),
+            challenger: &league_list("CHALLENGER"),
+        })
+        .unwrap();
+
+        recorder
+            .archives
+            .write(PlatformRoute::EUW1, timestamp, &line)
+            .unwrap();
+    }
+
+    #[test]
+    fn appends_records_to_hourly_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = LadderRecorder::new(dir.path().into(), vec![], 10);
+        let hour = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+
+        write_record(&recorder, hour);
+        write_record(&recorder, hour + chrono::Duration::minutes(1));
+
+        let archives = archives_in(&dir.path().join("EUW1")).unwrap();
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].ends_with("2024-07-01-12.jsonl.gz"));
+
+        let records = read_archive(&archives[0]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].timestamp, hour.timestamp_millis());
+        assert_eq!(records[1].region, "EUW1");
+    }
+
+    #[test]
+    fn removes_oldest_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = LadderRecorder::new(dir.path().into(), vec![], 2);
+        let hour = Utc.with_ymd_and_hms(2024, 7, 1, 12, 0, 0).unwrap();
+
+        for hours in 0..3 {
+            write_record(&recorder, hour + chrono::Duration::hours(hours));
+        }
+
+        let archives = archives_in(&dir.path().join("EUW1")).unwrap();
+        assert_eq!(archives.len(), 2);
+        assert!(archives[0].ends_with("2024-07-01-13.jsonl.gz"));
+        assert!(archives[1].ends_with("2024-07-01-14.jsonl.gz"));
+    }
+
+    #[test]
+    fn flush_writes_every_recorded_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = LadderRecorder::new(dir.path().into(), vec![], 10);
+        let (master, grandmaster, challenger) = (
+            league_list("MASTER"),
+            league_list("GRANDMASTER"),
+            league_list("CHALLENGER"),
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        recorder.record(PlatformRoute::EUW1, &master, &grandmaster, &challenger);
+                    }
+                });
+            }
+        });
+        recorder.flush();
+
+        let records: Vec<LadderRecord> = archives_in(&dir.path().join("EUW1"))
+            .unwrap()
+            .iter()
+            .flat_map(|archive| read_archive(archive).unwrap())
+            .collect();
+        assert_eq!(records.len(), 20);
+    }
+
+    #[test]
+    fn records_only_configured_regions() {
+        let recorder = LadderRecorder::new(PathBuf::new(), vec![PlatformRoute::EUW1], 10);
+
+        assert!(recorder.is_enabled(PlatformRoute::EUW1));
+        assert!(!recorder.is_enabled(PlatformRoute::NA1));
+    }
+}
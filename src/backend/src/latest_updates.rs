@@ -1,9 +1,7 @@
 use anyhow::Result;
 use log::info;
 use riven::consts::PlatformRoute;
-use sea_orm::{
-    sea_query::OnConflict, ActiveValue::Set, DatabaseConnection, DatabaseTransaction, EntityTrait,
-};
+use sea_orm::{sea_query::OnConflict, ActiveValue::Set, DatabaseConnection, EntityTrait};
 use tracing::instrument;
 
 use crate::entities::latest_updates;
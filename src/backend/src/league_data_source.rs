@@ -0,0 +1,277 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use riven::consts::{PlatformRoute, QueueType, RegionalRoute};
+use riven::models::account_v1::Account;
+use riven::models::league_v4::LeagueList;
+use riven::models::summoner_v4::Summoner;
+
+use crate::entities::sea_orm_active_enums::RankTierEnum;
+use crate::rate_budget::{Method, RATE_BUDGET};
+use crate::riot_api;
+
+/// Everything the pipeline reads from the Riot API. Implemented by [`RiotApiDataSource`] in
+/// production and by [`fake::FakeDataSource`] in tests, so a full region cycle can run without a
+/// network.
+#[async_trait]
+pub trait LeagueDataSource: Send + Sync {
+    /// The solo queue league list of an apex tier.
+    async fn get_league(&self, region: PlatformRoute, tier: RankTierEnum) -> Result<LeagueList>;
+
+    async fn get_summoner(&self, region: PlatformRoute, summoner_id: &str) -> Result<Summoner>;
+
+    async fn get_account(&self, route: RegionalRoute, puuid: &str) -> Result<Account>;
+
+    /// Waits until a query of `method` to `route`, e.g. `EUW1` or `EUROPE`, fits in the rate budget.
+    /// Called before every attempt of a query, outside of its timeout.
+    async fn acquire_budget(&self, route: &str, method: Method);
+}
+
+/// Reads from the Riot API through the client returned by [`riot_api::get`], so reloaded API keys
+/// are used by the next query. Shares the [`RATE_BUDGET`] with every region, the league lists with
+/// priority over the summoner and account lookups.
+pub struct RiotApiDataSource;
+
+#[async_trait]
+impl LeagueDataSource for RiotApiDataSource {
+    async fn get_league(&self, region: PlatformRoute, tier: RankTierEnum) -> Result<LeagueList> {
+        let api = riot_api::get();
+        let league_v4 = api.league_v4();
+        let queue = QueueType::RANKED_SOLO_5x5;
+
+        Ok(match tier {
+            RankTierEnum::Master => league_v4.get_master_league(region, queue).await?,
+            RankTierEnum::Grandmaster => league_v4.get_grandmaster_league(region, queue).await?,
+            RankTierEnum::Challenger => league_v4.get_challenger_league(region, queue).await?,
+        })
+    }
+
+    async fn get_summoner(&self, region: PlatformRoute, summoner_id: &str) -> Result<Summoner> {
+        Ok(riot_api::get()
+            .summoner_v4()
+            .get_by_summoner_id(region, summoner_id)
+            .await?)
+    }
+
+    async fn get_account(&self, route: RegionalRoute, puuid: &str) -> Result<Account> {
+        Ok(riot_api::get()
+            .account_v1()
+            .get_by_puuid(route, puuid)
+            .await?)
+    }
+
+    async fn acquire_budget(&self, route: &str, method: Method) {
+        RATE_BUDGET.acquire(route, method, method.priority()).await;
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Mutex;
+
+    use anyhow::anyhow;
+    use riven::models::league_v4::LeagueItem;
+    use sea_orm::ActiveEnum;
+
+    use super::*;
+    use crate::rate_budget::RateBudget;
+    use crate::test_fixtures::riot_api_error;
+
+    /// Serves scripted responses. Every league list request takes the next scripted list of its
+    /// region and tier, so a test scripts one list per tier for every cycle it runs.
+    #[derive(Default)]
+    pub struct FakeDataSource {
+        leagues: Mutex<HashMap<(PlatformRoute, String), VecDeque<LeagueList>>>,
+        summoners: HashMap<(PlatformRoute, String), Summoner>,
+        accounts: HashMap<String, Account>,
+        unavailable_clusters: Vec<RegionalRoute>,
+        cluster_errors: HashMap<RegionalRoute, u16>,
+        account_queries: Mutex<Vec<RegionalRoute>>,
+        rate_budget: Option<RateBudget>,
+    }
+
+    impl FakeDataSource {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn with_league(
+            self,
+            region: PlatformRoute,
+            tier: RankTierEnum,
+            entries: Vec<LeagueItem>,
+        ) -> Self {
+            // Built through serde, the riven models can not be constructed the same way across
+            // versions.
+            let mut league: LeagueList = serde_json::from_str(&format!(
+                r#"{{"tier": "{}", "queue": "RANKED_SOLO_5x5", "entries": []}}"#,
+                tier.to_value()
+            ))
+            .unwrap();
+            league.entries = entries;
+
+            self.leagues
+                .lock()
+                .unwrap()
+                .entry((region, tier.to_value()))
+                .or_default()
+                .push_back(league);
+
+            self
+        }
+
+        pub fn with_summoner(
+            mut self,
+            region: PlatformRoute,
+            summoner_id: &str,
+            puuid: &str,
+        ) -> Self {
+            let summoner = serde_json::from_str(&format!(
+                r#"{{"id": "{summoner_id}", "accountId": "account-{summoner_id}", "puuid": "{puuid}",
+                    "name": "", "profileIconId": 1, "revisionDate": 0, "summonerLevel": 100}}"#
+            ))
+            .unwrap();
+            self.summoners
+                .insert((region, summoner_id.to_string()), summoner);
+
+            self
+        }
+
+        pub fn with_account(mut self, puuid: &str, game_name: &str, tag_line: &str) -> Self {
+            let account = serde_json::from_str(&format!(
+                r#"{{"puuid": "{puuid}", "gameName": "{game_name}", "tagLine": "{tag_line}"}}"#
+            ))
+            .unwrap();
+            self.accounts.insert(puuid.to_string(), account);
+
+            self
+        }
+
+        /// Makes every account request to `route` time out.
+        pub fn with_unavailable_cluster(mut self, route: RegionalRoute) -> Self {
+            self.unavailable_clusters.push(route);
+
+            self
+        }
+
+        /// Makes every account request to `route` fail with a response with `status`.
+        pub fn with_cluster_error(mut self, route: RegionalRoute, status: u16) -> Self {
+            self.cluster_errors.insert(route, status);
+
+            self
+        }
+
+        /// The clusters of all account requests so far, in order.
+        pub fn account_queries(&self) -> Vec<RegionalRoute> {
+            self.account_queries.lock().unwrap().clone()
+        }
+
+        /// Makes every query wait for `rate_budget`, there is no budget otherwise.
+        pub fn with_rate_budget(mut self, rate_budget: RateBudget) -> Self {
+            self.rate_budget = Some(rate_budget);
+
+            self
+        }
+    }
+
+    #[async_trait]
+    impl LeagueDataSource for FakeDataSource {
+        async fn get_league(
+            &self,
+            region: PlatformRoute,
+            tier: RankTierEnum,
+        ) -> Result<LeagueList> {
+            self.leagues
+                .lock()
+                .unwrap()
+                .get_mut(&(region, tier.to_value()))
+                .and_then(|leagues| leagues.pop_front())
+                .ok_or_else(|| anyhow!("No scripted {:?} league left for {}", tier, region))
+        }
+
+        async fn get_summoner(&self, region: PlatformRoute, summoner_id: &str) -> Result<Summoner> {
+            self.summoners
+                .get(&(region, summoner_id.to_string()))
+                .cloned()
+                .ok_or_else(|| anyhow!("No scripted summoner {} in {}", summoner_id, region))
+        }
+
+        async fn get_account(&self, route: RegionalRoute, puuid: &str) -> Result<Account> {
+            self.account_queries.lock().unwrap().push(route);
+
+            if self.unavailable_clusters.contains(&route) {
+                return std::future::pending().await;
+            }
+            if let Some(&status) = self.cluster_errors.get(&route) {
+                return Err(riot_api_error(status, &[]).await.into());
+            }
+
+            self.accounts
+                .get(puuid)
+                .cloned()
+                .ok_or_else(|| anyhow!("No scripted account {}", puuid))
+        }
+
+        async fn acquire_budget(&self, route: &str, method: Method) {
+            if let Some(rate_budget) = &self.rate_budget {
+                rate_budget.acquire(route, method, method.priority()).await;
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeDataSource;
+    use super::*;
+
+    #[tokio::test]
+    async fn fake_serves_scripted_leagues_in_order() {
+        let source = FakeDataSource::new()
+            .with_league(PlatformRoute::EUW1, RankTierEnum::Master, vec![])
+            .with_league(PlatformRoute::EUW1, RankTierEnum::Master, vec![]);
+
+        assert!(source
+            .get_league(PlatformRoute::EUW1, RankTierEnum::Master)
+            .await
+            .is_ok());
+        assert!(source
+            .get_league(PlatformRoute::EUW1, RankTierEnum::Master)
+            .await
+            .is_ok());
+        assert!(source
+            .get_league(PlatformRoute::EUW1, RankTierEnum::Master)
+            .await
+            .is_err());
+        assert!(source
+            .get_league(PlatformRoute::NA1, RankTierEnum::Master)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn fake_serves_summoners_and_accounts() {
+        let source = FakeDataSource::new()
+            .with_summoner(PlatformRoute::EUW1, "summoner1", "puuid1")
+            .with_account("puuid1", "Player", "EUW");
+
+        let summoner = source
+            .get_summoner(PlatformRoute::EUW1, "summoner1")
+            .await
+            .unwrap();
+        assert_eq!(summoner.puuid, "puuid1");
+        assert!(source
+            .get_summoner(PlatformRoute::NA1, "summoner1")
+            .await
+            .is_err());
+
+        let account = source
+            .get_account(RegionalRoute::EUROPE, "puuid1")
+            .await
+            .unwrap();
+        assert_eq!(account.game_name.as_deref(), Some("Player"));
+    }
+}
@@ -1,11 +1,44 @@
+use std::env;
+use std::path::Path;
+
+use anyhow::{Context, Result};
 use chrono::Utc;
-use tracing::level_filters::LevelFilter;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use strum_macros::EnumString;
 use tracing_appender::{
-    non_blocking::WorkerGuard,
+    non_blocking::{NonBlocking, WorkerGuard},
     rolling::{RollingFileAppender, Rotation},
 };
 use tracing_subscriber::fmt::time::FormatTime;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::{LogFileConfig, LoggingConfig, OtlpConfig};
+use crate::metrics::MetricsLayer;
+
+/// How often a new log file is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
 
 struct CustomTimeFormatter;
 
@@ -16,47 +49,150 @@ impl FormatTime for CustomTimeFormatter {
     }
 }
 
-pub fn init() -> (WorkerGuard, WorkerGuard) {
-    let file_appender = RollingFileAppender::builder()
-        .rotation(Rotation::HOURLY)
-        .filename_prefix("dodgetracker-log")
-        .filename_suffix("log")
-        .max_log_files(72) // three days worth of logs
-        .build(".log/")
-        .unwrap();
+/// Flushes the buffered log lines and spans when dropped.
+pub struct LogGuards {
+    _file: Option<WorkerGuard>,
+    _json: Option<WorkerGuard>,
+    tracer_provider: Option<TracerProvider>,
+}
+
+impl Drop for LogGuards {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            if let Err(error) = tracer_provider.shutdown() {
+                eprintln!("Failed to export remaining spans: {:?}", error);
+            }
+        }
+    }
+}
+
+/// Batches spans and exports them to the OTLP collector in the background.
+fn otlp_tracer_provider(config: &OtlpConfig) -> Result<TracerProvider> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::Config::default().with_resource(
+            Resource::new([KeyValue::new("service.name", config.service_name.clone())]),
+        ))
+        .install_batch(runtime::Tokio)
+        .with_context(|| format!("Failed to set up OTLP exporter for {}", config.endpoint))
+}
 
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+/// Builds a fresh filter for each sink, since `EnvFilter` can't be cloned.
+fn env_filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::builder()
+        .parse(directives)
+        .with_context(|| format!("Invalid log filter \"{}\"", directives))
+}
 
-    // File appender for JSON logs
-    let json_appender = RollingFileAppender::builder()
-        .rotation(Rotation::DAILY)
-        .filename_prefix("dodgetracker-log-json")
+fn rolling_file_writer(
+    file_config: &LogFileConfig,
+    dir: &Path,
+    filename_prefix: &str,
+) -> Result<(NonBlocking, WorkerGuard)> {
+    let appender = RollingFileAppender::builder()
+        .rotation(file_config.rotation.into())
+        .filename_prefix(filename_prefix)
         .filename_suffix("log")
-        .max_log_files(3)
-        .build(".log/json/")
-        .unwrap();
+        .max_log_files(file_config.max_files)
+        .build(dir)
+        .with_context(|| format!("Failed to create log directory {}", dir.display()))?;
+
+    Ok(tracing_appender::non_blocking(appender))
+}
 
-    let (json_non_blocking, _json_guard) = tracing_appender::non_blocking(json_appender);
+/// Sets up the log sinks enabled in `config`, and exports spans if `otlp` is set. `RUST_LOG`
+/// overrides the configured filter. Has to be called from within the Tokio runtime.
+pub fn init(config: &LoggingConfig, otlp: Option<&OtlpConfig>) -> Result<LogGuards> {
+    let directives = env::var("RUST_LOG").unwrap_or_else(|_| config.filter.clone());
+
+    let file_appender = config
+        .text
+        .as_ref()
+        .map(|text| rolling_file_writer(text, &config.dir, "dodgetracker-log"))
+        .transpose()?;
+    let json_appender = config
+        .json
+        .as_ref()
+        .map(|json| rolling_file_writer(json, &config.dir.join("json"), "dodgetracker-log-json"))
+        .transpose()?;
+
+    let (non_blocking, _guard) = file_appender.unzip();
+    let (json_non_blocking, _json_guard) = json_appender.unzip();
 
     // Layer for formatted logs with custom time formatter
-    let fmt_layer = fmt::layer()
-        .with_timer(CustomTimeFormatter)
-        .with_target(false)
-        .with_writer(non_blocking)
-        .with_filter(LevelFilter::INFO);
+    let fmt_layer = non_blocking
+        .map(|writer| -> Result<_> {
+            Ok(fmt::layer()
+                .with_timer(CustomTimeFormatter)
+                .with_target(false)
+                .with_writer(writer)
+                .with_filter(env_filter(&directives)?))
+        })
+        .transpose()?;
 
     // Layer for JSON logs
-    let json_layer = fmt::layer()
-        .json()
-        .with_target(false)
-        .with_writer(json_non_blocking)
-        .with_filter(LevelFilter::INFO);
+    let json_layer = json_non_blocking
+        .map(|writer| -> Result<_> {
+            Ok(fmt::layer()
+                .json()
+                .with_target(false)
+                .with_writer(writer)
+                .with_filter(env_filter(&directives)?))
+        })
+        .transpose()?;
+
+    // Layer for stdout, e.g. when running under systemd without journald
+    let stdout_layer = config
+        .stdout
+        .then(|| -> Result<_> {
+            Ok(fmt::layer()
+                .with_timer(CustomTimeFormatter)
+                .with_target(false)
+                .with_writer(std::io::stdout)
+                .with_filter(env_filter(&directives)?))
+        })
+        .transpose()?;
+
+    // Layer for the systemd journal
+    let journald_layer = config
+        .journald
+        .then(|| -> Result<_> {
+            Ok(tracing_journald::layer()
+                .context("Failed to connect to journald")?
+                .with_filter(env_filter(&directives)?))
+        })
+        .transpose()?;
+
+    // Layer for OTLP trace export, a no-op if disabled
+    let tracer_provider = otlp.map(otlp_tracer_provider).transpose()?;
+    let otlp_layer = tracer_provider
+        .as_ref()
+        .map(|provider| -> Result<_> {
+            Ok(tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+                .with_filter(env_filter(&directives)?))
+        })
+        .transpose()?;
 
-    // Combine the layers
+    // Combine the layers. The metrics layer only filters by crate, so the metrics don't depend on
+    // the log level.
     tracing_subscriber::registry()
         .with(fmt_layer)
         .with(json_layer)
+        .with(stdout_layer)
+        .with(journald_layer)
+        .with(MetricsLayer::filtered())
+        .with(otlp_layer)
         .init();
 
-    (_guard, _json_guard)
+    Ok(LogGuards {
+        _file: _guard,
+        _json: _json_guard,
+        tracer_provider,
+    })
 }
@@ -1,25 +1,24 @@
-use core::error;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use anyhow::{anyhow, Context, Result};
 use futures::future::join_all;
+use riven::consts::PlatformRoute;
 use sea_orm::{sea_query::OnConflict, ActiveValue::Set, DatabaseTransaction, EntityTrait};
 use tracing::{error, info, instrument, warn};
 use urlencoding::encode;
 
 use crate::{
-    config::INSERT_CHUNK_SIZE,
+    config,
     entities::{lol_pros, riot_ids, sea_orm_active_enums::PositionEnum},
-    util::with_timeout,
+    error::PipelineError,
+    metrics,
+    util::retry::retry,
 };
 
-#[derive(Debug)]
-struct LolProsProfile {
-    slug: String,
-    name: String,
-    country: String,
-    position: PositionEnum,
-}
+/// lolpros.gg only lists accounts on the European servers. Its search is fuzzy, so looking up
+/// players of other regions would attach unrelated pro profiles to them.
+pub const LOLPROS_SUPPORTED_REGIONS: [PlatformRoute; 2] =
+    [PlatformRoute::EUW1, PlatformRoute::EUN1];
 
 async fn get_lolpros_slug(
     game_name: String,
@@ -30,6 +29,7 @@ async fn get_lolpros_slug(
 
     let response: Vec<serde_json::Value> = reqwest::get(&url)
         .await
+        .and_then(|response| response.error_for_status())
         .context("Failed to fetch data from API")?
         .json()
         .await
@@ -84,23 +84,27 @@ async fn get_lolpros_slug(
     }
 }
 
-#[instrument(skip(accounts, txn), fields(accounts = accounts.len()))]
+#[instrument(skip(accounts, region, txn), fields(accounts = accounts.len()))]
 pub async fn upsert_lolpros_slugs(
     accounts: &[riot_ids::ActiveModel],
+    region: PlatformRoute,
     txn: &DatabaseTransaction,
-) -> Result<()> {
+) -> Result<(), PipelineError> {
     let t1 = Instant::now();
     info!("Starting lolpros queries...");
 
+    let config = config::get();
     let results: Vec<_> = join_all(accounts.iter().map(|model| {
         let game_name = model.game_name.clone().unwrap();
         let tag_line = model.tag_line.clone().unwrap();
-        with_timeout(
-            Duration::from_secs(5),
-            get_lolpros_slug(game_name, tag_line),
-        )
+        retry(&config.retry, config.timeouts.lolpros, move || {
+            get_lolpros_slug(game_name.clone(), tag_line.clone())
+        })
     }))
     .await;
+    for result in &results {
+        metrics::record_api_result("lolpros", region, result);
+    }
 
     info!(
         perf = t1.elapsed().as_millis(),
@@ -114,7 +118,7 @@ pub async fn upsert_lolpros_slugs(
 
     for (model, result) in accounts.iter().zip(results) {
         match result {
-            Ok(Ok(Some(profile))) => {
+            Ok(Some(profile)) => {
                 riot_ids_to_upsert.push(riot_ids::ActiveModel {
                     puuid: Set(model.puuid.clone().unwrap()),
                     lolpros_slug: Set(Some(profile.slug.clone().unwrap())),
@@ -122,25 +126,16 @@ pub async fn upsert_lolpros_slugs(
                 });
                 slugs_to_upsert.push(profile);
             }
-            Ok(Err(e)) => {
-                warn!(
-                    game_name = model.game_name.clone().unwrap(),
-                    tag_line = model.game_name.clone().unwrap(),
-                    puuid = model.puuid.clone().unwrap(),
-                    error = ?e,
-                    "Lolpros API query failed. Ignoring.",
-                );
-            }
             Err(e) => {
                 warn!(
                     game_name = model.game_name.clone().unwrap(),
                     tag_line = model.game_name.clone().unwrap(),
                     puuid = model.puuid.clone().unwrap(),
                     error = ?e,
-                    "Lolpros API query timed out. Ignoring.",
+                    "Lolpros API query failed. Ignoring.",
                 );
             }
-            _ => {}
+            Ok(None) => {}
         }
     }
 
@@ -154,7 +149,7 @@ pub async fn upsert_lolpros_slugs(
         "Upserting lolpros slugs into riot_ids DB...",
     );
 
-    for chunk in riot_ids_to_upsert.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in riot_ids_to_upsert.chunks(config::get().insert_chunk_size) {
         riot_ids::Entity::insert_many(chunk.to_vec())
             .on_conflict(
                 OnConflict::column(riot_ids::Column::Puuid)
@@ -162,7 +157,8 @@ pub async fn upsert_lolpros_slugs(
                     .to_owned(),
             )
             .exec(txn)
-            .await?;
+            .await
+            .map_err(|error| PipelineError::LolPros(error.into()))?;
     }
 
     info!(
@@ -178,7 +174,7 @@ pub async fn upsert_lolpros_slugs(
         "Upserting lolpros profiles into lolpros DB...",
     );
 
-    for chunk in slugs_to_upsert.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in slugs_to_upsert.chunks(config::get().insert_chunk_size) {
         lol_pros::Entity::insert_many(chunk.to_vec())
             .on_conflict(
                 OnConflict::column(lol_pros::Column::Slug)
@@ -191,7 +187,8 @@ pub async fn upsert_lolpros_slugs(
                     .to_owned(),
             )
             .exec(txn)
-            .await?;
+            .await
+            .map_err(|error| PipelineError::LolPros(error.into()))?;
     }
 
     info!(
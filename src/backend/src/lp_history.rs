@@ -0,0 +1,110 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::Result;
+use riven::{consts::PlatformRoute, models::league_v4::LeagueItem};
+use sea_orm::{ActiveValue::Set, DatabaseTransaction, EntityTrait};
+use tracing::{info, instrument};
+
+use crate::{
+    config,
+    entities::{apex_tier_players, lp_history, sea_orm_active_enums::RankTierEnum},
+};
+
+/// Whether the ladder entry of a player differs from what is stored for it. Players that are not
+/// stored yet have no history, so their first entry is always a change.
+fn has_changed(new_data: &LeagueItem, old_data: Option<&apex_tier_players::Model>) -> bool {
+    match old_data {
+        None => true,
+        Some(old_data) => {
+            old_data.current_lp != new_data.league_points as i64
+                || old_data.wins != new_data.wins as i64
+                || old_data.losses != new_data.losses as i64
+        }
+    }
+}
+
+#[instrument(skip_all, fields(api_players = api_players.len(), db_players = db_players.len()))]
+pub async fn insert_lp_history(
+    api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
+    db_players: &HashMap<String, apex_tier_players::Model>,
+    region: PlatformRoute,
+    txn: &DatabaseTransaction,
+) -> Result<usize> {
+    let t1 = Instant::now();
+    info!("Finding LP changes...");
+
+    let history_models: Vec<lp_history::ActiveModel> = api_players
+        .iter()
+        .filter_map(|(summoner_id, (stats, rank_tier))| {
+            if has_changed(stats, db_players.get(summoner_id)) {
+                Some(lp_history::ActiveModel {
+                    summoner_id: Set(summoner_id.clone()),
+                    region: Set(region.to_string()),
+                    lp: Set(stats.league_points as i64),
+                    wins: Set(stats.wins as i64),
+                    losses: Set(stats.losses as i64),
+                    rank_tier: Set(rank_tier.clone()),
+                    ..Default::default()
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    info!(
+        perf = t1.elapsed().as_millis(),
+        changes = history_models.len(),
+        metric = "lp_change_detection",
+        "Found LP changes."
+    );
+
+    let t2 = Instant::now();
+
+    for chunk in history_models.chunks(config::get().insert_chunk_size) {
+        lp_history::Entity::insert_many(chunk.to_vec())
+            .exec(txn)
+            .await?;
+    }
+
+    info!(
+        perf = t2.elapsed().as_millis(),
+        changes = history_models.len(),
+        metric = "lp_history_inserted",
+        "Inserted LP history into DB."
+    );
+
+    Ok(history_models.len())
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{db_player, league_item};
+
+    #[test]
+    fn new_player_has_changed() {
+        assert!(has_changed(&league_item("a", 100, 10, 5), None));
+    }
+
+    #[test]
+    fn unchanged_player_has_not_changed() {
+        assert!(!has_changed(
+            &league_item("a", 100, 10, 5),
+            Some(&db_player("a", 100, 10, 5))
+        ));
+    }
+
+    #[test]
+    fn detects_lp_and_game_changes() {
+        let old = db_player("a", 100, 10, 5);
+
+        assert!(has_changed(&league_item("a", 95, 10, 5), Some(&old)));
+        assert!(has_changed(&league_item("a", 120, 11, 5), Some(&old)));
+        // A loss at 0 LP keeps the LP the same but still changes the record
+        assert!(has_changed(&league_item("a", 100, 10, 6), Some(&old)));
+    }
+}
@@ -1,213 +1,238 @@
 extern crate dotenv;
-use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::process::exit;
+use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::Result;
-use futures::future::join_all;
-use lazy_static::lazy_static;
-use riven::consts::PlatformRoute;
-use sea_orm::ActiveValue::Set;
-use sea_orm::TransactionTrait;
-use tokio::spawn;
-use tokio::time::sleep;
+use futures::future::try_join_all;
+use tokio::select;
+use tokio::sync::watch;
 use tokio::time::Duration;
 use tracing::instrument;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use crate::config::{Config, RegionConfig};
+use crate::dodge_detectors::DetectorKind;
+use crate::error::Recovery;
+use crate::ladder_recorder::LADDER_RECORDER;
+use crate::league_data_source::{LeagueDataSource, RiotApiDataSource};
+use crate::pause::REGION_PAUSE;
+use crate::polling::AdaptivePolling;
+use crate::status::REGION_STATUS;
+use crate::supervisor::RestartPolicy;
 
 mod apex_tier_players;
 mod config;
+mod cycle;
 mod db;
+mod decays;
+mod dodge_confidence;
+mod dodge_detectors;
 mod dodges;
 mod entities;
+mod error;
+#[cfg(test)]
+mod integration_tests;
+mod ladder_recorder;
 mod latest_updates;
+mod league_data_source;
 mod logger;
 mod lolpros;
+mod lp_history;
+mod metrics;
+mod pause;
 mod player_counts;
+mod polling;
 mod promotions_demotions;
+mod rate_budget;
+mod replay;
 mod riot_api;
 mod riot_ids;
+mod shutdown;
+mod status;
 mod summoners;
+mod supervisor;
+#[cfg(test)]
+mod test_fixtures;
 mod util;
 
-const SUPPORTED_REGIONS: [PlatformRoute; 5] = [
-    PlatformRoute::EUW1,
-    PlatformRoute::EUN1,
-    PlatformRoute::NA1,
-    PlatformRoute::KR,
-    PlatformRoute::OC1,
-];
-
-lazy_static! {
-    static ref THROTTLES: HashMap<PlatformRoute, Duration> = {
-        let mut m = HashMap::new();
-        m.insert(PlatformRoute::EUW1, Duration::from_millis(1300));
-        m.insert(PlatformRoute::EUN1, Duration::from_millis(1300));
-        m.insert(PlatformRoute::NA1, Duration::from_millis(1300));
-        m.insert(PlatformRoute::KR, Duration::from_millis(1300));
-        m.insert(PlatformRoute::OC1, Duration::from_millis(1300));
-        m
-    };
-}
-
-const RETRY_WAIT_SECS: u64 = 5;
-
 #[instrument(skip_all, fields(duration = duration.as_millis()))]
-async fn sleep_thread(duration: Duration) {
+async fn sleep_thread(duration: Duration, shutdown: &mut watch::Receiver<bool>) {
     info!("Sleeping...");
-    sleep(duration).await;
+    shutdown::sleep_unless_requested(duration, shutdown).await;
 }
 
-#[allow(unreachable_code)]
-#[instrument(name = "run")]
-async fn run_region(region: PlatformRoute) {
+/// Updates the region until shutdown is requested. A running update is never interrupted, so its
+/// transaction is either committed or rolled back before this returns.
+#[instrument(name = "run", skip_all, fields(region = %region_config.region))]
+async fn run_region(
+    region_config: &RegionConfig,
+    source: Arc<dyn LeagueDataSource>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let region = region_config.region;
+
     info!("Getting DB connection...");
     let db = db::get_db().await;
 
-    loop {
+    info!(detector = %region_config.detector, "Using dodge detector.");
+    let detector = region_config.detector.detector();
+    let mut polling = AdaptivePolling::new(region_config.poll_interval);
+
+    while !shutdown::is_requested(&shutdown) {
+        REGION_PAUSE.wait(&mut shutdown).await;
+        if shutdown::is_requested(&shutdown) {
+            break;
+        }
+
         let t1 = Instant::now();
+        let key_generation = riot_api::key_generation();
 
-        info!("Starting transaction...");
-        let txn = match db.begin().await {
-            Ok(txn) => txn,
-            Err(e) => {
-                error!(?e, "Failed to start transaction");
-                sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-                continue;
+        match cycle::run_cycle(region, source.as_ref(), detector.as_ref(), db).await {
+            Ok(stats) => {
+                REGION_STATUS.record_success(region, t1.elapsed(), stats.dodges);
+                polling.record_update(t1, stats.lp_changes);
             }
-        };
-
-        let t2 = Instant::now();
-        let (api_players, (master_count, grandmaster_count, challenger_count)) =
-            match apex_tier_players::get_players_from_api(region).await {
-                Ok(r) => r,
-                Err(error) => {
-                    error!(?error, "Error getting players from the League API.");
-                    sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-                    continue;
-                }
-            };
-
-        let db_players = match apex_tier_players::get_players_from_db(&txn, region).await {
-            Ok(res) => res,
             Err(error) => {
-                error!(?error, "Error getting players from DB.");
-                sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-                continue;
-            }
-        };
-
-        let dodges = dodges::find_dodges(&db_players, &api_players).await;
-
-        if !dodges.is_empty() {
-            let summoner_ids: Vec<&str> = dodges
-                .iter()
-                .filter_map(|dodge| match &dodge.summoner_id {
-                    Set(id) => Some(id.as_str()),
-                    _ => None,
-                })
-                .collect();
-
-            let riot_ids = match summoners::upsert_summoners(&summoner_ids, region, &txn).await {
-                Ok(res) => res,
-                Err(error) => {
-                    error!(?error, "Error updating summoners table");
-                    sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-                    continue;
+                REGION_STATUS.record_failure(region, &error);
+
+                match error.recovery() {
+                    Recovery::Retry(wait) => {
+                        error!(?error, "Region update failed.");
+                        sleep_thread(wait, &mut shutdown).await;
+                    }
+                    // The key was replaced while the cycle ran, so retry with the new one
+                    Recovery::PauseAllRegions(_)
+                        if key_generation != riot_api::key_generation() => {}
+                    Recovery::PauseAllRegions(pause) => {
+                        if riot_api::mark_key_rejected(key_generation) {
+                            error!(
+                                alert = true,
+                                ?error,
+                                pause_secs = pause.as_secs(),
+                                "Riot API rejected the API key, pausing all regions. Send SIGHUP after updating RIOT_API_KEY to resume."
+                            );
+                            metrics::record_api_key_rejected();
+                        } else {
+                            warn!(
+                                ?error,
+                                "Riot API key is still rejected, pausing all regions."
+                            );
+                        }
+                        REGION_PAUSE.pause_for(pause);
+                    }
                 }
-            };
-
-            let riot_id_models = match riot_ids::update_riot_ids(&riot_ids, &txn).await {
-                Ok(res) => res,
-                Err(error) => {
-                    error!(?error, "Error updating riot_ids table");
-                    sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-                    continue;
-                }
-            };
-
-            if region == PlatformRoute::EUW1 {
-                if let Err(error) = lolpros::upsert_lolpros_slugs(&riot_id_models, &txn).await {
-                    error!(?error, "Error upserting Lolpros slugs. Ignoring.");
-                }
-            }
-
-            if let Err(error) = dodges::insert_dodges(&dodges, &txn).await {
-                error!(?error, "Error inserting dodges");
-                sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
                 continue;
             }
         }
 
-        if let Err(error) = apex_tier_players::upsert_players(&api_players, region, &txn).await {
-            error!(?error, "Error upserting players");
-            sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-            continue;
-        }
-
-        if let Err(error) =
-            promotions_demotions::insert_promotions(&api_players, &db_players, region, &txn).await
-        {
-            error!(?error, "Error inserting promotions");
-            sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-            continue;
-        }
-        if let Err(error) =
-            promotions_demotions::insert_demotions(&api_players, &db_players, region, &txn).await
-        {
-            error!(?error, "Error inserting demotions");
-            sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-            continue;
-        }
-
-        if let Err(error) = player_counts::update_player_counts(
-            master_count,
-            grandmaster_count,
-            challenger_count,
-            region,
-            &txn,
-        )
-        .await
-        {
-            error!(?error, "Error updating player counts. Ignoring.");
-        }
-
-        info!("Committing transaction...");
-        if let Err(error) = txn.commit().await {
-            error!(?error, "Failed to commit transaction.");
-            sleep_thread(Duration::from_secs(RETRY_WAIT_SECS)).await;
-            continue;
-        }
-        info!(
-            perf = t1.elapsed().as_millis(),
-            metric = "region_update",
-            "Region update complete.",
-        );
-        if let Err(error) = latest_updates::set_latest_update(region, db).await {
-            error!(?error, "Error setting latest update time. Ignoring.");
-        }
-
-        if let Some(sleep_duration) = THROTTLES[&region].checked_sub(t2.elapsed()) {
-            sleep_thread(sleep_duration).await;
+        let poll_interval = polling.interval();
+        metrics::record_poll_interval(region, poll_interval);
+        if let Some(sleep_duration) = poll_interval.checked_sub(t1.elapsed()) {
+            sleep_thread(sleep_duration, &mut shutdown).await;
         }
     }
+
+    info!("Region stopped.");
 }
 
 async fn run() -> Result<()> {
-    let mut tasks = vec![];
+    let source: Arc<dyn LeagueDataSource> = Arc::new(RiotApiDataSource);
+    let shutdown = shutdown::listen();
+    riot_api::listen_for_reload();
+    let restart_policy = RestartPolicy::default();
 
-    for &region in SUPPORTED_REGIONS.iter() {
-        tasks.push(spawn(async move { run_region(region).await }));
+    for region_config in &config::get().regions {
+        REGION_STATUS.register(region_config.region);
+    }
+    if let Some(status_server) = &config::get().status_server {
+        status::serve(&REGION_STATUS, status_server.clone(), shutdown.clone()).await?;
     }
 
-    // Wait for all tasks to complete and collect the results
-    let _results = join_all(tasks).await;
+    // Every region runs in its own task, so a panic only takes down that region. The supervisors
+    // restart crashed regions, and fail the whole process if a region keeps crashing.
+    try_join_all(config::get().regions.iter().map(|region_config| {
+        let region = region_config.region.to_string();
+        let source = source.clone();
+        let shutdown = shutdown.clone();
+        let restart_policy = &restart_policy;
+
+        async move {
+            supervisor::supervise(&region, restart_policy, shutdown.clone(), || {
+                run_region(region_config, source.clone(), shutdown.clone())
+            })
+            .await
+        }
+    }))
+    .await?;
+
+    info!("All regions stopped.");
 
     Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    let (_guard, _json_guard) = logger::init();
+    // The logger depends on the config, so config errors can only be printed
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Failed to load config: {:?}", error);
+            exit(1);
+        }
+    };
+    config::init(config);
+
+    let log_guards = match logger::init(&config::get().logging, config::get().otlp.as_ref()) {
+        Ok(log_guards) => log_guards,
+        Err(error) => {
+            eprintln!("Failed to set up logging: {:?}", error);
+            exit(1);
+        }
+    };
 
-    run().await.unwrap();
+    let exit_code = select! {
+        exit_code = run_command() => exit_code,
+        _ = shutdown::forced_exit() => shutdown::FORCED_EXIT_CODE,
+    };
+
+    if let Some(recorder) = LADDER_RECORDER.as_ref() {
+        tokio::task::block_in_place(|| recorder.flush());
+    }
+
+    // `exit` skips destructors, so the non-blocking log writers and the span exporter have to be
+    // flushed first
+    drop(log_guards);
+    exit(exit_code);
+}
+
+/// Runs the command given on the command line and returns the exit code.
+async fn run_command() -> i32 {
+    let args: Vec<String> = env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("replay") => {
+            let (Some(dir), Ok(detector_kind)) = (
+                args.get(2),
+                args.get(3)
+                    .map_or(Ok(DetectorKind::RuleBased), |kind| kind.parse()),
+            ) else {
+                eprintln!("Usage: {} replay <dir> [rule_based|strict]", args[0]);
+                return 2;
+            };
+
+            replay::run(Path::new(dir), detector_kind).await
+        }
+        _ => run().await,
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            error!(?error, "Exiting with error.");
+            eprintln!("{:?}", error);
+            1
+        }
+    }
 }
@@ -0,0 +1,316 @@
+use anyhow::Result;
+use lazy_static::lazy_static;
+use prometheus::{
+    exponential_buckets, register_gauge_vec, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, Encoder, GaugeVec, HistogramVec, IntCounter, IntCounterVec,
+    TextEncoder,
+};
+use riven::consts::PlatformRoute;
+use riven::RiotApiError;
+use tokio::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{Filtered, LevelFilter, Targets};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::util::is_timeout;
+
+/// Used for stage durations logged outside of any region span.
+const UNKNOWN_REGION: &str = "unknown";
+
+lazy_static! {
+    static ref STAGE_DURATION: HistogramVec = register_histogram_vec!(
+        "dodgetracker_stage_duration_seconds",
+        "Duration of the pipeline stages, from the `perf` field of their `metric` log events.",
+        &["stage", "region"],
+        // 5ms to ~40s
+        exponential_buckets(0.005, 2.0, 14).unwrap()
+    )
+    .unwrap();
+    static ref DODGES: IntCounterVec = register_int_counter_vec!(
+        "dodgetracker_dodges_total",
+        "Committed dodges.",
+        &["region"]
+    )
+    .unwrap();
+    static ref PROMOTIONS: IntCounterVec = register_int_counter_vec!(
+        "dodgetracker_promotions_total",
+        "Committed promotions into the apex tiers.",
+        &["region"]
+    )
+    .unwrap();
+    static ref DEMOTIONS: IntCounterVec = register_int_counter_vec!(
+        "dodgetracker_demotions_total",
+        "Committed demotions out of the apex tiers.",
+        &["region"]
+    )
+    .unwrap();
+    static ref API_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "dodgetracker_api_errors_total",
+        "Failed API queries. `kind` is the HTTP status, \"timeout\" or \"error\".",
+        &["api", "region", "kind"]
+    )
+    .unwrap();
+    static ref API_KEY_REJECTIONS: IntCounter = register_int_counter!(
+        "dodgetracker_api_key_rejections_total",
+        "Region updates that paused every region because the Riot API rejected the API key."
+    )
+    .unwrap();
+    static ref REGION_RESTARTS: IntCounterVec = register_int_counter_vec!(
+        "dodgetracker_region_restarts_total",
+        "Restarts of region tasks after they crashed.",
+        &["region"]
+    )
+    .unwrap();
+    static ref POLL_INTERVAL: GaugeVec = register_gauge_vec!(
+        "dodgetracker_poll_interval_seconds",
+        "Current minimum time between the start of two updates of a region.",
+        &["region"]
+    )
+    .unwrap();
+    static ref RATE_BUDGET_USAGE: GaugeVec = register_gauge_vec!(
+        "dodgetracker_rate_budget_usage_ratio",
+        "Used fraction of the fullest rate limit window of a route, as of its last query. `scope` is \"application\" or the method.",
+        &["route", "scope"]
+    )
+    .unwrap();
+    static ref RATE_BUDGET_WAIT: HistogramVec = register_histogram_vec!(
+        "dodgetracker_rate_budget_wait_seconds",
+        "How long API queries were deferred because the rate budget was used up.",
+        &["route", "method"],
+        // 5ms to ~40s
+        exponential_buckets(0.005, 2.0, 14).unwrap()
+    )
+    .unwrap();
+}
+
+/// Counts what a committed region update inserted.
+pub fn record_cycle(region: PlatformRoute, dodges: usize, promotions: usize, demotions: usize) {
+    let region = region.to_string();
+    DODGES.with_label_values(&[&region]).inc_by(dodges as u64);
+    PROMOTIONS
+        .with_label_values(&[&region])
+        .inc_by(promotions as u64);
+    DEMOTIONS
+        .with_label_values(&[&region])
+        .inc_by(demotions as u64);
+}
+
+/// Counts `result` as a failed API query unless it succeeded. Retried queries are only counted once.
+pub fn record_api_result<T>(api: &str, region: PlatformRoute, result: &Result<T>) {
+    let Err(error) = result else {
+        return;
+    };
+
+    let kind = if is_timeout(error) {
+        "timeout".to_string()
+    } else {
+        match error
+            .downcast_ref::<RiotApiError>()
+            .and_then(|error| error.status_code())
+        {
+            Some(status) => status.as_u16().to_string(),
+            None => "error".to_string(),
+        }
+    };
+
+    API_ERRORS
+        .with_label_values(&[api, &region.to_string(), &kind])
+        .inc();
+}
+
+pub fn record_poll_interval(region: PlatformRoute, interval: Duration) {
+    POLL_INTERVAL
+        .with_label_values(&[&region.to_string()])
+        .set(interval.as_secs_f64());
+}
+
+pub fn record_api_key_rejected() {
+    API_KEY_REJECTIONS.inc();
+}
+
+pub fn record_region_restart(region: &str) {
+    REGION_RESTARTS.with_label_values(&[region]).inc();
+}
+
+pub fn record_rate_budget_usage(route: &str, scope: &str, usage: f64) {
+    RATE_BUDGET_USAGE
+        .with_label_values(&[route, scope])
+        .set(usage);
+}
+
+pub fn record_rate_budget_wait(route: &str, method: &str, wait: Duration) {
+    RATE_BUDGET_WAIT
+        .with_label_values(&[route, method])
+        .observe(wait.as_secs_f64());
+}
+
+/// Every metric in the Prometheus text format.
+pub fn encode() -> Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+/// Records the `perf` field of every log event with a `metric` field in the stage duration
+/// histogram. The region is taken from the event or the closest span with a `region` field.
+pub struct MetricsLayer;
+
+impl MetricsLayer {
+    /// Only enabled for the spans and events of this crate, at any level. Without a filter, every
+    /// callsite of every dependency would be enabled, whatever the log filter of the other layers.
+    pub fn filtered<S>() -> Filtered<MetricsLayer, Targets, S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        MetricsLayer
+            .with_filter(Targets::new().with_target(env!("CARGO_CRATE_NAME"), LevelFilter::TRACE))
+    }
+}
+
+/// The `region` field of a span.
+struct SpanRegion(String);
+
+#[derive(Default)]
+struct FieldVisitor {
+    perf_millis: Option<u128>,
+    metric: Option<String>,
+    region: Option<String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_u128(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if let Ok(value) = u128::try_from(value) {
+            self.record_u128(field, value);
+        }
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        if field.name() == "perf" {
+            self.perf_millis = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "metric" => self.metric = Some(value.to_string()),
+            "region" => self.region = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        // `region = %region` fields are recorded as debug values
+        if field.name() == "region" {
+            self.region = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+
+        if let (Some(region), Some(span)) = (visitor.region, ctx.span(id)) {
+            span.extensions_mut().insert(SpanRegion(region));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let (Some(perf_millis), Some(metric)) = (visitor.perf_millis, visitor.metric) else {
+            return;
+        };
+
+        let region = visitor
+            .region
+            .or_else(|| {
+                ctx.event_scope(event)?.find_map(|span| {
+                    span.extensions()
+                        .get::<SpanRegion>()
+                        .map(|region| region.0.clone())
+                })
+            })
+            .unwrap_or_else(|| UNKNOWN_REGION.to_string());
+
+        STAGE_DURATION
+            .with_label_values(&[&metric, &region])
+            .observe(perf_millis as f64 / 1000.0);
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+    use tracing::{info, info_span};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::util::with_timeout;
+
+    #[test]
+    fn records_perf_events_with_region_of_span() {
+        let subscriber = tracing_subscriber::registry().with(MetricsLayer::filtered());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = info_span!("run", region = %PlatformRoute::KR).entered();
+            info!(perf = 1500u128, metric = "test_stage", "Stage complete.");
+            info!(
+                perf = 20u128,
+                metric = "test_stage",
+                region = "OC1",
+                "Stage complete."
+            );
+            info!(perf = 20u128, "Not a metric.");
+            info!(
+                target: "riven",
+                perf = 20u128,
+                metric = "test_stage",
+                "Not from this crate."
+            );
+        });
+
+        let kr = STAGE_DURATION.with_label_values(&["test_stage", "KR"]);
+        assert_eq!(kr.get_sample_count(), 1);
+        assert_eq!(kr.get_sample_sum(), 1.5);
+        let oc1 = STAGE_DURATION.with_label_values(&["test_stage", "OC1"]);
+        assert_eq!(oc1.get_sample_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn counts_failed_api_queries() {
+        let api = "test_api";
+        let timeout = || with_timeout(Duration::ZERO, std::future::pending::<()>());
+        record_api_result(api, PlatformRoute::TW2, &Ok(()));
+        record_api_result::<()>(api, PlatformRoute::TW2, &Err(anyhow!("failed")));
+        record_api_result(api, PlatformRoute::TW2, &timeout().await);
+        record_api_result(api, PlatformRoute::TW2, &timeout().await);
+
+        let count = |kind| API_ERRORS.with_label_values(&[api, "TW2", kind]).get();
+        assert_eq!(count("error"), 1);
+        assert_eq!(count("timeout"), 2);
+
+        let metrics = encode().unwrap();
+        assert!(metrics.contains(
+            "dodgetracker_api_errors_total{api=\"test_api\",kind=\"timeout\",region=\"TW2\"} 2"
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use lazy_static::lazy_static;
+use tokio::select;
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::info;
+
+use crate::shutdown;
+
+lazy_static! {
+    /// Pauses every region, e.g. while the Riot API rejects the API key.
+    pub static ref REGION_PAUSE: RegionPause = RegionPause::default();
+}
+
+pub struct RegionPause {
+    /// When the regions may continue, `None` if they are not paused.
+    paused_until: watch::Sender<Option<Instant>>,
+}
+
+impl Default for RegionPause {
+    fn default() -> Self {
+        RegionPause {
+            paused_until: watch::channel(None).0,
+        }
+    }
+}
+
+impl RegionPause {
+    /// Pauses the regions for `duration`. An earlier pause that ends later is kept.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+
+        self.paused_until.send_if_modified(|paused_until| {
+            if paused_until.is_some_and(|paused_until| paused_until >= until) {
+                return false;
+            }
+            *paused_until = Some(until);
+            true
+        });
+    }
+
+    /// Ends the current pause, e.g. after a new API key was loaded.
+    pub fn resume(&self) {
+        self.paused_until.send_replace(None);
+    }
+
+    /// Waits until the regions are no longer paused, or until shutdown is requested.
+    pub async fn wait(&self, shutdown: &mut watch::Receiver<bool>) {
+        let mut paused_until = self.paused_until.subscribe();
+
+        loop {
+            let Some(until) = *paused_until.borrow_and_update() else {
+                return;
+            };
+            if until <= Instant::now() || shutdown::is_requested(shutdown) {
+                return;
+            }
+
+            info!(
+                remaining = (until - Instant::now()).as_millis(),
+                "All regions are paused, waiting..."
+            );
+            select! {
+                _ = sleep_until(until) => {}
+                _ = paused_until.changed() => {}
+                _ = shutdown.changed() => {}
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_until_pause_ends() {
+        let (_sender, mut shutdown) = watch::channel(false);
+        let pause = RegionPause::default();
+        let t1 = Instant::now();
+
+        pause.pause_for(Duration::from_millis(20));
+        pause.pause_for(Duration::from_millis(1));
+        pause.wait(&mut shutdown).await;
+
+        assert!(t1.elapsed() >= Duration::from_millis(20));
+        pause.wait(&mut shutdown).await;
+        assert!(t1.elapsed() < Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn resume_ends_pause() {
+        let (_sender, mut shutdown) = watch::channel(false);
+        let pause = Arc::new(RegionPause::default());
+        let t1 = Instant::now();
+
+        pause.pause_for(Duration::from_secs(60));
+        let resumer = pause.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            resumer.resume();
+        });
+        pause.wait(&mut shutdown).await;
+
+        assert!(t1.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use crate::config::PollInterval;
+
+/// How much the latest update moves the observed LP change rate. Smooths out single updates that
+/// happen to see many or few changes.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Adapts the poll interval of a region to how fast its ladder changes. The busier the ladder, the
+/// sooner a dodge can be masked by the next game of the player, so the interval is chosen to see
+/// about `target_lp_changes` changes per update.
+pub struct AdaptivePolling {
+    config: PollInterval,
+    /// Smoothed LP changes per second, `None` until two updates succeeded.
+    change_rate: Option<f64>,
+    /// Start of the last successful update. The changes of an update are the changes since then,
+    /// because failed updates don't store anything.
+    last_update: Option<Instant>,
+}
+
+impl AdaptivePolling {
+    pub fn new(config: PollInterval) -> Self {
+        AdaptivePolling {
+            config,
+            change_rate: None,
+            last_update: None,
+        }
+    }
+
+    /// Records a successful update that started at `started` and found `lp_changes` changes.
+    pub fn record_update(&mut self, started: Instant, lp_changes: usize) {
+        // The first update compares against whatever was stored before the backend started
+        if let Some(elapsed) = self
+            .last_update
+            .map(|last_update| started.duration_since(last_update))
+            .filter(|elapsed| !elapsed.is_zero())
+        {
+            let rate = lp_changes as f64 / elapsed.as_secs_f64();
+            self.change_rate = Some(match self.change_rate {
+                Some(change_rate) => change_rate + RATE_SMOOTHING * (rate - change_rate),
+                None => rate,
+            });
+        }
+
+        self.last_update = Some(started);
+    }
+
+    /// The minimum time between the start of this update and the next.
+    pub fn interval(&self) -> Duration {
+        let PollInterval {
+            min,
+            max,
+            target_lp_changes,
+        } = self.config;
+
+        match self.change_rate {
+            None => min,
+            Some(rate) if rate <= 0.0 => max,
+            Some(rate) => {
+                Duration::from_secs_f64((target_lp_changes / rate).min(max.as_secs_f64()))
+                    .clamp(min, max)
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polling() -> AdaptivePolling {
+        AdaptivePolling::new(PollInterval {
+            min: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+            target_lp_changes: 4.0,
+        })
+    }
+
+    #[test]
+    fn polls_faster_when_ladder_is_busy() {
+        let mut polling = polling();
+        let t1 = Instant::now();
+
+        polling.record_update(t1, 1000);
+        assert_eq!(polling.interval(), Duration::from_secs(1));
+
+        // 1 change per second
+        polling.record_update(t1 + Duration::from_secs(2), 2);
+        assert_eq!(polling.interval(), Duration::from_secs(4));
+
+        // 8 changes per second, smoothed to 3.1
+        polling.record_update(t1 + Duration::from_secs(3), 8);
+        let interval = polling.interval();
+        assert!(
+            interval > Duration::from_secs(1) && interval < Duration::from_secs(2),
+            "{interval:?}"
+        );
+
+        for seconds in 4..20 {
+            polling.record_update(t1 + Duration::from_secs(seconds), 8);
+        }
+        assert_eq!(polling.interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backs_off_when_ladder_is_quiet() {
+        let mut polling = polling();
+        let t1 = Instant::now();
+
+        polling.record_update(t1, 0);
+        polling.record_update(t1 + Duration::from_secs(1), 0);
+        assert_eq!(polling.interval(), Duration::from_secs(10));
+
+        polling.record_update(t1 + Duration::from_secs(11), 1);
+        assert_eq!(polling.interval(), Duration::from_secs(10));
+    }
+}
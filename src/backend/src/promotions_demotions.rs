@@ -7,7 +7,7 @@ use sea_orm::{ActiveValue::Set, ColumnTrait, DatabaseTransaction, EntityTrait, Q
 use tracing::{info, instrument};
 
 use crate::{
-    config::INSERT_CHUNK_SIZE,
+    config,
     entities::{apex_tier_players, demotions, promotions, sea_orm_active_enums::RankTierEnum},
 };
 
@@ -73,22 +73,16 @@ async fn get_demotions(
     Ok(result)
 }
 
-#[instrument(skip_all, fields(api_players = api_players.len(), db_players = db_players.len()))]
-pub async fn insert_promotions(
+pub fn find_promotions(
     api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
     db_players: &HashMap<String, apex_tier_players::Model>,
+    demotions: &HashMap<String, Vec<DateTime<FixedOffset>>>,
     region: PlatformRoute,
-    txn: &DatabaseTransaction,
-) -> Result<()> {
-    let demotions = get_demotions(region, txn).await?;
-
-    let t1 = Instant::now();
-    info!("Finding promotions...");
-
-    let promotions_models: Vec<promotions::ActiveModel> = api_players
+) -> Vec<promotions::ActiveModel> {
+    api_players
         .iter()
         .filter_map(|(summoner_id, (stats, _))| {
-            if has_promoted(summoner_id, db_players, &demotions) {
+            if has_promoted(summoner_id, db_players, demotions) {
                 Some(promotions::ActiveModel {
                     summoner_id: Set(summoner_id.clone()),
                     region: Set(region.to_string()),
@@ -100,7 +94,56 @@ pub async fn insert_promotions(
                 None
             }
         })
-        .collect();
+        .collect()
+}
+
+pub fn find_players_not_in_api(
+    api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
+    db_players: &HashMap<String, apex_tier_players::Model>,
+) -> HashMap<String, apex_tier_players::Model> {
+    db_players
+        .iter()
+        .filter(|(summoner_id, _)| !api_players.contains_key(*summoner_id))
+        .map(|(summoner_id, player)| (summoner_id.clone(), player.clone()))
+        .collect()
+}
+
+pub fn find_demotions(
+    players_not_in_api: &HashMap<String, apex_tier_players::Model>,
+    demotions: &HashMap<String, Vec<DateTime<FixedOffset>>>,
+    region: PlatformRoute,
+) -> Vec<demotions::ActiveModel> {
+    players_not_in_api
+        .iter()
+        .filter_map(|(summoner_id, player)| {
+            if has_demoted(player, demotions) {
+                Some(demotions::ActiveModel {
+                    summoner_id: Set(summoner_id.clone()),
+                    region: Set(region.to_string()),
+                    at_wins: Set(player.wins),
+                    at_losses: Set(player.losses),
+                    ..Default::default()
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[instrument(skip_all, fields(api_players = api_players.len(), db_players = db_players.len()))]
+pub async fn insert_promotions(
+    api_players: &HashMap<String, (LeagueItem, RankTierEnum)>,
+    db_players: &HashMap<String, apex_tier_players::Model>,
+    region: PlatformRoute,
+    txn: &DatabaseTransaction,
+) -> Result<usize> {
+    let demotions = get_demotions(region, txn).await?;
+
+    let t1 = Instant::now();
+    info!("Finding promotions...");
+
+    let promotions_models = find_promotions(api_players, db_players, &demotions, region);
 
     info!(
         perf = t1.elapsed().as_millis(),
@@ -109,12 +152,12 @@ pub async fn insert_promotions(
         "Found promotions."
     );
 
-    for chunk in promotions_models.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in promotions_models.chunks(config::get().insert_chunk_size) {
         promotions::Entity::insert_many(chunk.to_vec())
             .exec(txn)
             .await?;
     }
-    Ok(())
+    Ok(promotions_models.len())
 }
 
 #[instrument(skip_all, fields(api_players = api_players.len(), db_players = db_players.len()))]
@@ -123,15 +166,11 @@ pub async fn insert_demotions(
     db_players: &HashMap<String, apex_tier_players::Model>,
     region: PlatformRoute,
     txn: &DatabaseTransaction,
-) -> Result<()> {
+) -> Result<usize> {
     let t1 = Instant::now();
     info!("Finding players not in API...");
 
-    let players_not_in_api: HashMap<String, apex_tier_players::Model> = db_players
-        .iter()
-        .filter(|(summoner_id, _)| !api_players.contains_key(*summoner_id))
-        .map(|(summoner_id, player)| (summoner_id.clone(), player.clone()))
-        .collect();
+    let players_not_in_api = find_players_not_in_api(api_players, db_players);
 
     info!(
         perf = t1.elapsed().as_millis(),
@@ -144,22 +183,7 @@ pub async fn insert_demotions(
     info!("Detecting demotions...");
 
     let t2 = Instant::now();
-    let demotion_models: Vec<demotions::ActiveModel> = players_not_in_api
-        .iter()
-        .filter_map(|(summoner_id, player)| {
-            if has_demoted(player, &demotions) {
-                Some(demotions::ActiveModel {
-                    summoner_id: Set(summoner_id.clone()),
-                    region: Set(region.to_string()),
-                    at_wins: Set(player.wins),
-                    at_losses: Set(player.losses),
-                    ..Default::default()
-                })
-            } else {
-                None
-            }
-        })
-        .collect();
+    let demotion_models = find_demotions(&players_not_in_api, &demotions, region);
 
     info!(
         perf = t2.elapsed().as_millis(),
@@ -168,13 +192,13 @@ pub async fn insert_demotions(
         "Detected demotions."
     );
 
-    for chunk in demotion_models.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in demotion_models.chunks(config::get().insert_chunk_size) {
         demotions::Entity::insert_many(chunk.to_vec())
             .exec(txn)
             .await?;
     }
 
-    Ok(())
+    Ok(demotion_models.len())
 }
 
 // ----------------------------------------------------------------------------------------------------------------------------------------
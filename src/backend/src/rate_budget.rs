@@ -0,0 +1,326 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::debug;
+
+use crate::{config, metrics};
+
+lazy_static! {
+    /// Shared by every region, so the regions can't exceed the limits of the API key together.
+    pub static ref RATE_BUDGET: RateBudget = RateBudget::new(config::get().rate_limits.clone());
+}
+
+/// At most `requests` requests in every `window`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window: Duration,
+}
+
+impl RateLimit {
+    const fn new(requests: u32, window_secs: u64) -> Self {
+        RateLimit {
+            requests,
+            window: Duration::from_secs(window_secs),
+        }
+    }
+}
+
+/// The Riot API limits of the API key. The application limits apply to every route, e.g. `EUW1` or
+/// `EUROPE`, on their own. The method limits apply to every route and method on their own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimits {
+    pub application: Vec<RateLimit>,
+    /// Shared by the master, grandmaster and challenger league lists.
+    pub league: Vec<RateLimit>,
+    pub summoner: Vec<RateLimit>,
+    pub account: Vec<RateLimit>,
+    /// The fraction of every limit that enrichment queries may use. The rest is kept for the league
+    /// lists. Between 0 and 1.
+    pub enrichment_share: f64,
+}
+
+impl Default for RateLimits {
+    /// The limits of a production API key.
+    fn default() -> Self {
+        RateLimits {
+            application: vec![RateLimit::new(500, 10), RateLimit::new(30000, 600)],
+            league: vec![RateLimit::new(90, 10), RateLimit::new(1500, 600)],
+            summoner: vec![RateLimit::new(1600, 60)],
+            account: vec![RateLimit::new(1000, 60)],
+            enrichment_share: 0.8,
+        }
+    }
+}
+
+impl RateLimits {
+    fn limits(&self, scope: Scope) -> &[RateLimit] {
+        match scope {
+            Scope::Application => &self.application,
+            Scope::Method(Method::League) => &self.league,
+            Scope::Method(Method::Summoner) => &self.summoner,
+            Scope::Method(Method::Account) => &self.account,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    League,
+    Summoner,
+    Account,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::League => "league",
+            Method::Summoner => "summoner",
+            Method::Account => "account",
+        }
+    }
+
+    pub fn priority(self) -> Priority {
+        match self {
+            Method::League => Priority::Detection,
+            Method::Summoner | Method::Account => Priority::Enrichment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// The league list polls that detect dodges. They may use the whole budget.
+    Detection,
+    /// The summoner and account lookups of the dodgers. They are deferred once the enrichment share
+    /// of a limit is used up.
+    Enrichment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Scope {
+    Application,
+    Method(Method),
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::Application => "application",
+            Scope::Method(method) => method.as_str(),
+        }
+    }
+}
+
+/// The requests sent within the last window of a limit.
+struct Window {
+    limit: RateLimit,
+    sent: VecDeque<Instant>,
+}
+
+impl Window {
+    fn new(limit: RateLimit) -> Self {
+        Window {
+            limit,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .sent
+            .front()
+            .is_some_and(|&sent| now.duration_since(sent) >= self.limit.window)
+        {
+            self.sent.pop_front();
+        }
+    }
+
+    fn capacity(&self, priority: Priority, enrichment_share: f64) -> usize {
+        let requests = self.limit.requests as usize;
+
+        match priority {
+            Priority::Detection => requests,
+            Priority::Enrichment => ((requests as f64 * enrichment_share) as usize).max(1),
+        }
+    }
+
+    /// How long until another request fits in `capacity`.
+    fn wait(&self, now: Instant, capacity: usize) -> Duration {
+        if self.sent.len() < capacity {
+            return Duration::ZERO;
+        }
+
+        let oldest_counted = self.sent[self.sent.len() - capacity];
+        (oldest_counted + self.limit.window).saturating_duration_since(now)
+    }
+
+    fn usage(&self) -> f64 {
+        self.sent.len() as f64 / self.limit.requests as f64
+    }
+}
+
+/// Schedules the Riot API queries of every region within the rate limits, before riven or the Riot
+/// API would have to reject them.
+pub struct RateBudget {
+    limits: RateLimits,
+    windows: Mutex<HashMap<(String, Scope), Vec<Window>>>,
+}
+
+impl RateBudget {
+    pub fn new(limits: RateLimits) -> Self {
+        RateBudget {
+            limits,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request of `method` to `route` if it fits in every limit, otherwise returns how
+    /// long to wait before trying again.
+    fn try_acquire(
+        &self,
+        route: &str,
+        method: Method,
+        priority: Priority,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let mut windows = self.windows.lock().unwrap();
+        let scopes = [Scope::Application, Scope::Method(method)];
+
+        let mut wait = Duration::ZERO;
+        for scope in scopes {
+            let windows = windows
+                .entry((route.to_string(), scope))
+                .or_insert_with(|| {
+                    self.limits
+                        .limits(scope)
+                        .iter()
+                        .map(|&limit| Window::new(limit))
+                        .collect()
+                });
+
+            for window in windows.iter_mut() {
+                window.prune(now);
+                let capacity = window.capacity(priority, self.limits.enrichment_share);
+                wait = wait.max(window.wait(now, capacity));
+            }
+        }
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+
+        for scope in scopes {
+            let windows = windows.get_mut(&(route.to_string(), scope)).unwrap();
+            for window in windows.iter_mut() {
+                window.sent.push_back(now);
+            }
+
+            let usage = windows.iter().map(Window::usage).fold(0.0, f64::max);
+            metrics::record_rate_budget_usage(route, scope.as_str(), usage);
+        }
+
+        Ok(())
+    }
+
+    /// Waits until a request of `method` to `route` fits in the budget, and counts it.
+    pub async fn acquire(&self, route: &str, method: Method, priority: Priority) {
+        let t1 = Instant::now();
+
+        while let Err(wait) = self.try_acquire(route, method, priority, Instant::now()) {
+            sleep(wait).await;
+        }
+
+        let waited = t1.elapsed();
+        if !waited.is_zero() {
+            debug!(
+                route,
+                method = method.as_str(),
+                ?priority,
+                waited = waited.as_millis(),
+                "Deferred API query, the rate budget was used up."
+            );
+            metrics::record_rate_budget_wait(route, method.as_str(), waited);
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget() -> RateBudget {
+        RateBudget::new(RateLimits {
+            application: vec![RateLimit::new(10, 1)],
+            league: vec![RateLimit::new(10, 1)],
+            summoner: vec![RateLimit::new(4, 10)],
+            account: vec![],
+            enrichment_share: 0.5,
+        })
+    }
+
+    #[test]
+    fn keeps_budget_for_detection() {
+        let budget = budget();
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            assert!(budget
+                .try_acquire("EUW1", Method::Account, Priority::Enrichment, now)
+                .is_ok());
+        }
+        // Half of the application limit is used up, enrichment has to wait for the window to pass
+        assert_eq!(
+            budget.try_acquire("EUW1", Method::Account, Priority::Enrichment, now),
+            Err(Duration::from_secs(1))
+        );
+
+        for _ in 0..5 {
+            assert!(budget
+                .try_acquire("EUW1", Method::League, Priority::Detection, now)
+                .is_ok());
+        }
+        assert!(budget
+            .try_acquire("EUW1", Method::League, Priority::Detection, now)
+            .is_err());
+    }
+
+    #[test]
+    fn applies_method_limits_and_windows() {
+        let budget = budget();
+        let now = Instant::now();
+
+        for seconds in 0..2 {
+            let now = now + Duration::from_secs(seconds);
+            assert!(budget
+                .try_acquire("EUW1", Method::Summoner, Priority::Enrichment, now)
+                .is_ok());
+        }
+        assert_eq!(
+            budget.try_acquire(
+                "EUW1",
+                Method::Summoner,
+                Priority::Enrichment,
+                now + Duration::from_secs(2)
+            ),
+            Err(Duration::from_secs(8))
+        );
+        assert!(budget
+            .try_acquire(
+                "EUW1",
+                Method::Summoner,
+                Priority::Enrichment,
+                now + Duration::from_secs(10)
+            )
+            .is_ok());
+
+        // Every route has its own budget
+        assert!(budget
+            .try_acquire("KR", Method::Summoner, Priority::Enrichment, now)
+            .is_ok());
+    }
+}
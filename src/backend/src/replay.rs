@@ -0,0 +1,479 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use riven::consts::PlatformRoute;
+use riven::models::league_v4::{LeagueItem, LeagueList};
+use sea_orm::ActiveEnum;
+use serde::Serialize;
+use tracing::{info, instrument};
+
+use crate::apex_tier_players::{self, ApiPlayers};
+use crate::decays::{self, RECENT_DECAY_WINDOW_HOURS};
+use crate::dodge_detectors::{DetectorKind, DodgeDetector, MaskedDodgeDetector};
+use crate::dodges::{self, DODGE_PENALTY_WINDOW_HOURS};
+use crate::entities::apex_tier_players as apex_tier_players_entity;
+use crate::entities::sea_orm_active_enums::RankTierEnum;
+use crate::promotions_demotions;
+use crate::{config, ladder_recorder};
+
+/// The league lists of one region as they were returned by the League API at `timestamp`.
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub master: Vec<LeagueItem>,
+    pub grandmaster: Vec<LeagueItem>,
+    pub challenger: Vec<LeagueItem>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ReplayEvent {
+    Dodge {
+        time: String,
+        region: String,
+        summoner_id: String,
+        lp_before: i64,
+        lp_after: i64,
+        rank_tier: String,
+        dodge_type: String,
+        masked_by_game: bool,
+        confidence: f64,
+        confidence_reasons: Vec<String>,
+    },
+    Promotion {
+        time: String,
+        region: String,
+        summoner_id: String,
+        at_wins: i64,
+        at_losses: i64,
+    },
+    Demotion {
+        time: String,
+        region: String,
+        summoner_id: String,
+        at_wins: i64,
+        at_losses: i64,
+    },
+}
+
+/// In-memory replacement for the tables that detection reads from, updated the same way a region
+/// cycle updates the DB.
+struct Replay {
+    region: PlatformRoute,
+    detector: Box<dyn DodgeDetector>,
+    masked_detector: Option<MaskedDodgeDetector>,
+    players: HashMap<String, apex_tier_players_entity::Model>,
+    dodges: HashMap<String, Vec<DateTime<Utc>>>,
+    decays: HashMap<String, Vec<DateTime<Utc>>>,
+    demotions: HashMap<String, Vec<DateTime<FixedOffset>>>,
+}
+
+impl Replay {
+    fn new(region: PlatformRoute, detector: Box<dyn DodgeDetector>) -> Self {
+        Replay {
+            region,
+            detector,
+            masked_detector: config::get()
+                .masked_dodges
+                .as_ref()
+                .map(MaskedDodgeDetector::new),
+            players: HashMap::new(),
+            dodges: HashMap::new(),
+            decays: HashMap::new(),
+            demotions: HashMap::new(),
+        }
+    }
+
+    fn recent_dodges(&self, now: DateTime<Utc>) -> HashSet<String> {
+        recent(&self.dodges, now, DODGE_PENALTY_WINDOW_HOURS)
+    }
+
+    fn recent_decays(&self, now: DateTime<Utc>) -> HashSet<String> {
+        recent(&self.decays, now, RECENT_DECAY_WINDOW_HOURS)
+    }
+
+    fn update_players(&mut self, api_players: ApiPlayers, time: DateTime<FixedOffset>) {
+        for (summoner_id, (entry, rank_tier)) in api_players {
+            let created_at = self
+                .players
+                .get(&summoner_id)
+                .map_or(time, |player| player.created_at);
+
+            self.players.insert(
+                summoner_id.clone(),
+                apex_tier_players_entity::Model {
+                    summoner_id,
+                    summoner_name: None,
+                    region: self.region.to_string(),
+                    current_lp: entry.league_points as i64,
+                    created_at,
+                    updated_at: time,
+                    wins: entry.wins as i64,
+                    losses: entry.losses as i64,
+                    rank_tier,
+                },
+            );
+        }
+    }
+
+    /// Runs detection for one snapshot and returns the events it found. The first snapshot only
+    /// seeds the ladder, otherwise every player in it would be reported as a promotion.
+    async fn apply(&mut self, snapshot: Snapshot) -> Vec<ReplayEvent> {
+        let time: DateTime<FixedOffset> = snapshot.timestamp.into();
+        let time_str = snapshot.timestamp.to_rfc3339();
+
+        let api_players = apex_tier_players::merge_apex_tiers([
+            (snapshot.master, RankTierEnum::Master),
+            (snapshot.grandmaster, RankTierEnum::Grandmaster),
+            (snapshot.challenger, RankTierEnum::Challenger),
+        ]);
+
+        if self.players.is_empty() {
+            self.update_players(api_players, time);
+            return vec![];
+        }
+
+        let recent_history = dodges::RecentHistory {
+            dodges: self.recent_dodges(snapshot.timestamp),
+            decays: self.recent_decays(snapshot.timestamp),
+            ..dodges::RecentHistory::at(snapshot.timestamp)
+        };
+        let dodges = dodges::find_dodges(
+            &self.players,
+            &api_players,
+            &recent_history,
+            self.detector.as_ref(),
+            self.masked_detector.as_ref(),
+        )
+        .await;
+        let decays = decays::find_decays(&self.players, &api_players, self.detector.as_ref()).await;
+        let promotions = promotions_demotions::find_promotions(
+            &api_players,
+            &self.players,
+            &self.demotions,
+            self.region,
+        );
+        let players_not_in_api =
+            promotions_demotions::find_players_not_in_api(&api_players, &self.players);
+        let demotions =
+            promotions_demotions::find_demotions(&players_not_in_api, &self.demotions, self.region);
+
+        let mut events = Vec::with_capacity(dodges.len() + promotions.len() + demotions.len());
+
+        for dodge in dodges {
+            let summoner_id = dodge.summoner_id.unwrap();
+            self.dodges
+                .entry(summoner_id.clone())
+                .or_default()
+                .push(snapshot.timestamp);
+
+            events.push(ReplayEvent::Dodge {
+                time: time_str.clone(),
+                region: dodge.region.unwrap(),
+                summoner_id,
+                lp_before: dodge.lp_before.unwrap(),
+                lp_after: dodge.lp_after.unwrap(),
+                rank_tier: dodge.rank_tier.unwrap().to_value(),
+                dodge_type: dodge.dodge_type.unwrap().to_value(),
+                masked_by_game: dodge.masked_by_game.unwrap(),
+                confidence: dodge.confidence.unwrap(),
+                confidence_reasons: dodge.confidence_reasons.unwrap(),
+            });
+        }
+
+        for decay in decays {
+            self.decays
+                .entry(decay.summoner_id.unwrap())
+                .or_default()
+                .push(snapshot.timestamp);
+        }
+
+        for promotion in promotions {
+            events.push(ReplayEvent::Promotion {
+                time: time_str.clone(),
+                region: promotion.region.unwrap(),
+                summoner_id: promotion.summoner_id.unwrap(),
+                at_wins: promotion.at_wins.unwrap(),
+                at_losses: promotion.at_losses.unwrap(),
+            });
+        }
+
+        for demotion in demotions {
+            let summoner_id = demotion.summoner_id.unwrap();
+            self.demotions
+                .entry(summoner_id.clone())
+                .or_default()
+                .push(time);
+
+            events.push(ReplayEvent::Demotion {
+                time: time_str.clone(),
+                region: demotion.region.unwrap(),
+                summoner_id,
+                at_wins: demotion.at_wins.unwrap(),
+                at_losses: demotion.at_losses.unwrap(),
+            });
+        }
+
+        self.update_players(api_players, time);
+
+        events
+    }
+}
+
+/// The players with an event within the last `window_hours` before `now`.
+fn recent(
+    events: &HashMap<String, Vec<DateTime<Utc>>>,
+    now: DateTime<Utc>,
+    window_hours: i64,
+) -> HashSet<String> {
+    let window = chrono::Duration::hours(window_hours);
+
+    events
+        .iter()
+        .filter(|(_, times)| times.iter().any(|&time| now - time < window))
+        .map(|(summoner_id, _)| summoner_id.clone())
+        .collect()
+}
+
+fn read_league_list(path: &Path) -> Result<LeagueList> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Loads the snapshots of one region. Snapshots are either the records of the ladder recorder's
+/// archives, or directories named after the unix timestamp in milliseconds at which they were
+/// recorded, containing the raw `master.json`, `grandmaster.json` and `challenger.json` League API
+/// responses.
+fn load_snapshots(region_dir: &Path) -> Result<Vec<Snapshot>> {
+    let mut snapshots = vec![];
+
+    for archive in ladder_recorder::archives_in(region_dir)? {
+        for record in ladder_recorder::read_archive(&archive)? {
+            let timestamp = DateTime::from_timestamp_millis(record.timestamp)
+                .ok_or_else(|| anyhow!("Invalid record timestamp in {}", archive.display()))?;
+
+            snapshots.push(Snapshot {
+                timestamp,
+                master: record.master.entries,
+                grandmaster: record.grandmaster.entries,
+                challenger: record.challenger.entries,
+            });
+        }
+    }
+
+    for entry in fs::read_dir(region_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        let timestamp = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<i64>().ok())
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(|| anyhow!("Invalid snapshot directory name: {}", path.display()))?;
+
+        snapshots.push(Snapshot {
+            timestamp,
+            master: read_league_list(&path.join("master.json"))?.entries,
+            grandmaster: read_league_list(&path.join("grandmaster.json"))?.entries,
+            challenger: read_league_list(&path.join("challenger.json"))?.entries,
+        });
+    }
+
+    snapshots.sort_by_key(|snapshot| snapshot.timestamp);
+
+    Ok(snapshots)
+}
+
+/// Replays every region directory in `dir` (named after its platform route, e.g. `EUW1`) and
+/// writes the detected events to stdout as JSON lines. Nothing is read from or written to the DB.
+#[instrument(skip_all, fields(dir = %dir.display(), detector = %detector_kind))]
+pub async fn run(dir: &Path, detector_kind: DetectorKind) -> Result<()> {
+    let mut region_dirs: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read replay directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    region_dirs.retain(|path| path.is_dir());
+    region_dirs.sort();
+
+    let mut stdout = std::io::stdout().lock();
+
+    for region_dir in region_dirs {
+        let region = region_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| PlatformRoute::from_str(name).ok())
+            .ok_or_else(|| anyhow!("Invalid region directory: {}", region_dir.display()))?;
+
+        let snapshots = load_snapshots(&region_dir)?;
+        info!(%region, snapshots = snapshots.len(), "Replaying region...");
+
+        let mut replay = Replay::new(region, detector_kind.detector());
+        let mut event_count = 0;
+
+        for snapshot in snapshots {
+            for event in replay.apply(snapshot).await {
+                writeln!(stdout, "{}", serde_json::to_string(&event)?)?;
+                event_count += 1;
+            }
+        }
+
+        info!(%region, events = event_count, "Replayed region.");
+    }
+
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::league_item;
+
+    fn snapshot(minutes: i64, master: Vec<LeagueItem>, grandmaster: Vec<LeagueItem>) -> Snapshot {
+        Snapshot {
+            timestamp: DateTime::from_timestamp(1_720_000_000, 0).unwrap()
+                + chrono::Duration::minutes(minutes),
+            master,
+            grandmaster,
+            challenger: vec![],
+        }
+    }
+
+    fn event_kinds(events: &[ReplayEvent]) -> Vec<(&'static str, String)> {
+        let mut kinds: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                ReplayEvent::Dodge { summoner_id, .. } => ("dodge", summoner_id.clone()),
+                ReplayEvent::Promotion { summoner_id, .. } => ("promotion", summoner_id.clone()),
+                ReplayEvent::Demotion { summoner_id, .. } => ("demotion", summoner_id.clone()),
+            })
+            .collect();
+        kinds.sort();
+        kinds
+    }
+
+    #[tokio::test]
+    async fn first_snapshot_only_seeds_ladder() {
+        let mut replay = Replay::new(PlatformRoute::EUW1, DetectorKind::RuleBased.detector());
+
+        let events = replay
+            .apply(snapshot(0, vec![league_item("a", 100, 10, 5)], vec![]))
+            .await;
+
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn replays_dodges_promotions_and_demotions() {
+        let mut replay = Replay::new(PlatformRoute::EUW1, DetectorKind::RuleBased.detector());
+
+        replay
+            .apply(snapshot(
+                0,
+                vec![league_item("a", 100, 10, 5)],
+                vec![league_item("b", 300, 20, 10)],
+            ))
+            .await;
+
+        let events = replay
+            .apply(snapshot(
+                1,
+                vec![league_item("a", 95, 10, 5), league_item("c", 0, 5, 5)],
+                vec![],
+            ))
+            .await;
+
+        assert_eq!(
+            event_kinds(&events),
+            vec![
+                ("demotion", "b".to_string()),
+                ("dodge", "a".to_string()),
+                ("promotion", "c".to_string()),
+            ]
+        );
+
+        let events = replay
+            .apply(snapshot(
+                2,
+                vec![league_item("a", 80, 10, 5), league_item("c", 0, 5, 5)],
+                vec![league_item("b", 300, 20, 10)],
+            ))
+            .await;
+
+        assert_eq!(
+            event_kinds(&events),
+            vec![("dodge", "a".to_string()), ("promotion", "b".to_string())]
+        );
+        assert!(events.contains(&ReplayEvent::Dodge {
+            time: snapshot(2, vec![], vec![]).timestamp.to_rfc3339(),
+            region: "EUW1".to_string(),
+            summoner_id: "a".to_string(),
+            lp_before: 95,
+            lp_after: 80,
+            rank_tier: "MASTER".to_string(),
+            dodge_type: "REPEAT_OFFENCE".to_string(),
+            masked_by_game: false,
+            confidence: 1.0,
+            confidence_reasons: vec!["matches_penalty".to_string()],
+        }));
+    }
+
+    #[tokio::test]
+    async fn does_not_repeat_demotions() {
+        let mut replay = Replay::new(PlatformRoute::EUW1, DetectorKind::RuleBased.detector());
+
+        replay
+            .apply(snapshot(
+                0,
+                vec![league_item("a", 100, 10, 5), league_item("b", 0, 1, 1)],
+                vec![],
+            ))
+            .await;
+        let events = replay
+            .apply(snapshot(1, vec![league_item("a", 100, 10, 5)], vec![]))
+            .await;
+        assert_eq!(event_kinds(&events), vec![("demotion", "b".to_string())]);
+
+        let events = replay
+            .apply(snapshot(2, vec![league_item("a", 100, 10, 5)], vec![]))
+            .await;
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lowers_confidence_after_replayed_decay() {
+        let mut replay = Replay::new(PlatformRoute::EUW1, DetectorKind::RuleBased.detector());
+
+        replay
+            .apply(snapshot(0, vec![league_item("a", 100, 10, 5)], vec![]))
+            .await;
+        let events = replay
+            .apply(snapshot(1, vec![league_item("a", 25, 10, 5)], vec![]))
+            .await;
+        assert!(events.is_empty());
+
+        let events = replay
+            .apply(snapshot(2, vec![league_item("a", 10, 10, 5)], vec![]))
+            .await;
+        let [ReplayEvent::Dodge {
+            confidence_reasons, ..
+        }] = events.as_slice()
+        else {
+            panic!("Expected a single dodge: {events:?}");
+        };
+        assert_eq!(
+            confidence_reasons,
+            &vec!["matches_penalty".to_string(), "recent_decay".to_string()]
+        );
+    }
+}
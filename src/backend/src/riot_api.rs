@@ -1,12 +1,173 @@
 use std::env;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 
-use dotenv::from_path;
+use anyhow::{bail, Context, Result};
 use lazy_static::lazy_static;
-use riven::RiotApi;
+use riven::{RiotApi, RiotApiConfig};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+use crate::pause::REGION_PAUSE;
+
+/// Read again on every reload, so a rotated key can be loaded without a restart.
+const ENV_FILE: &str = "../../.env";
 
 lazy_static! {
-    pub static ref RIOT_API: RiotApi = {
-        from_path("../../.env").ok();
-        RiotApi::new(env::var("RIOT_API_KEY").expect("RIOT API KEY not set"))
+    static ref RIOT_API: RwLock<ApiKeyState> = RwLock::new(ApiKeyState::new(
+        read_api_key(Path::new(ENV_FILE)).expect("RIOT API KEY not set")
+    ));
+}
+
+struct ApiKeyState {
+    api: Arc<RiotApi>,
+    key: String,
+    /// Incremented whenever a new key is loaded.
+    generation: u64,
+    rejected: bool,
+}
+
+impl ApiKeyState {
+    fn new(key: String) -> Self {
+        ApiKeyState {
+            api: Arc::new(riot_api(&key)),
+            key,
+            generation: 0,
+            rejected: false,
+        }
+    }
+
+    /// Returns `false` if `key` is the key already in use.
+    fn replace_key(&mut self, key: String) -> bool {
+        if key == self.key {
+            return false;
+        }
+
+        *self = ApiKeyState {
+            generation: self.generation + 1,
+            ..ApiKeyState::new(key)
+        };
+        true
+    }
+
+    /// Returns `true` only for the first rejection of the key of `generation`.
+    fn mark_rejected(&mut self, generation: u64) -> bool {
+        if generation != self.generation || self.rejected {
+            return false;
+        }
+
+        self.rejected = true;
+        true
+    }
+}
+
+fn riot_api(key: &str) -> RiotApi {
+    // Failed queries are retried by `util::retry` instead, so the retries of all APIs are
+    // configured in one place and bounded by the query timeouts. It waits for the `Retry-After` of
+    // a 429 like riven would.
+    RiotApi::new(RiotApiConfig::with_key(key).set_retries(0))
+}
+
+/// `RIOT_API_KEY` from `env_file` if it sets it, otherwise from the environment.
+fn read_api_key(env_file: &Path) -> Result<String> {
+    // `from_path` never overrides variables that are already set, so it can't reload the key
+    #[allow(deprecated)]
+    let from_file = match dotenv::from_path_iter(env_file) {
+        Ok(vars) => vars
+            .filter_map(|var| var.ok())
+            .find_map(|(name, value)| (name == "RIOT_API_KEY").then_some(value)),
+        Err(_) => None,
+    };
+    let key = match from_file {
+        Some(key) => key,
+        None => env::var("RIOT_API_KEY").context("RIOT_API_KEY is not set")?,
     };
+
+    let key = key.trim().to_string();
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_graphic()) {
+        bail!("RIOT_API_KEY is not a valid API key");
+    }
+
+    Ok(key)
+}
+
+/// The client with the current API key. Queries that already started keep their client when a new
+/// key is loaded.
+pub fn get() -> Arc<RiotApi> {
+    RIOT_API.read().unwrap().api.clone()
+}
+
+/// Identifies the current API key, for [`mark_key_rejected`].
+pub fn key_generation() -> u64 {
+    RIOT_API.read().unwrap().generation
+}
+
+/// Marks the key of `generation` as rejected by the Riot API. Returns `true` if it is still the
+/// current key and wasn't rejected before, so every key is only alerted on once.
+pub fn mark_key_rejected(generation: u64) -> bool {
+    RIOT_API.write().unwrap().mark_rejected(generation)
+}
+
+/// Loads the API key again. Returns `false` if it did not change.
+pub fn reload() -> Result<bool> {
+    let key = read_api_key(Path::new(ENV_FILE))?;
+    Ok(RIOT_API.write().unwrap().replace_key(key))
+}
+
+/// Reloads the API key on every SIGHUP, and resumes all regions if it changed.
+pub fn listen_for_reload() {
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading Riot API key...");
+
+            match reload() {
+                Ok(true) => {
+                    info!("Loaded new Riot API key, resuming all regions.");
+                    REGION_PAUSE.resume();
+                }
+                Ok(false) => warn!("Riot API key is unchanged."),
+                Err(error) => error!(?error, "Failed to reload Riot API key."),
+            }
+        }
+    });
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reads_api_key_from_env_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let env_file = dir.path().join(".env");
+
+        fs::write(&env_file, "DB_HOST=localhost\nRIOT_API_KEY=RGAPI-new\n").unwrap();
+        assert_eq!(read_api_key(&env_file).unwrap(), "RGAPI-new");
+
+        fs::write(&env_file, "RIOT_API_KEY=\"RGAPI new\"\n").unwrap();
+        assert!(read_api_key(&env_file).is_err());
+    }
+
+    #[test]
+    fn alerts_once_per_rejected_key() {
+        let mut state = ApiKeyState::new("RGAPI-old".to_string());
+        let old_generation = state.generation;
+
+        assert!(state.mark_rejected(old_generation));
+        assert!(!state.mark_rejected(old_generation));
+
+        assert!(!state.replace_key("RGAPI-old".to_string()));
+        assert!(state.replace_key("RGAPI-new".to_string()));
+
+        // A query that failed with the old key doesn't reject the new one
+        assert!(!state.mark_rejected(old_generation));
+        assert!(state.mark_rejected(state.generation));
+    }
 }
@@ -1,32 +1,117 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{Error, Result};
 use futures::future::join_all;
+use riven::consts::{PlatformRoute, RegionalRoute};
+use riven::models::account_v1::Account;
+use riven::RiotApiError;
 use sea_orm::sea_query::OnConflict;
 use sea_orm::DatabaseTransaction;
 use sea_orm::{ActiveValue::Set, EntityTrait};
-use tracing::{error, info, instrument};
+use tracing::{error, info, instrument, warn};
 
-use crate::config::INSERT_CHUNK_SIZE;
-use crate::util::with_timeout;
-use crate::{entities::riot_ids, riot_api::RIOT_API};
+use crate::rate_budget::Method;
+use crate::util::is_timeout;
+use crate::util::retry::retry_when_ready;
+use crate::{config, metrics};
+use crate::{entities::riot_ids, league_data_source::LeagueDataSource};
+
+/// The regional clusters serving account-v1.
+const ACCOUNT_CLUSTERS: [RegionalRoute; 4] = [
+    RegionalRoute::AMERICAS,
+    RegionalRoute::ASIA,
+    RegionalRoute::EUROPE,
+    RegionalRoute::SEA,
+];
+
+/// The clusters to query for accounts of `region`, starting with the cluster of the region itself.
+/// Accounts are global, so every other cluster can serve them as well.
+fn account_routes(region: PlatformRoute) -> Vec<RegionalRoute> {
+    let cluster = region.to_regional();
+
+    std::iter::once(cluster)
+        .chain(
+            ACCOUNT_CLUSTERS
+                .into_iter()
+                .filter(|&route| route != cluster),
+        )
+        .collect()
+}
+
+/// Only a cluster that is down or overloaded fails where another cluster may succeed. Anything
+/// else, like a missing account, a rejected API key or a rate limit, fails on every cluster.
+fn is_cluster_failure(error: &Error) -> bool {
+    if is_timeout(error) {
+        return true;
+    }
+
+    error.chain().any(|cause| {
+        if let Some(error) = cause.downcast_ref::<RiotApiError>() {
+            // Riven errors without a status never got a response
+            return error
+                .status_code()
+                .is_none_or(|status| status.is_server_error());
+        }
+
+        cause.downcast_ref::<reqwest::Error>().is_some_and(|error| {
+            error.is_timeout()
+                || error.is_connect()
+                || error
+                    .status()
+                    .is_some_and(|status| status.is_server_error())
+        })
+    })
+}
+
+/// Gets an account from the cluster of `region`, falling back to the other clusters if it is
+/// unavailable.
+async fn get_account(
+    source: &dyn LeagueDataSource,
+    region: PlatformRoute,
+    puuid: &str,
+) -> Result<Account> {
+    let mut last_error = None;
+
+    for route in account_routes(region) {
+        let config = config::get();
+        let route_name = route.to_string();
+        let result = retry_when_ready(
+            &config.retry,
+            config.timeouts.account,
+            || source.acquire_budget(&route_name, Method::Account),
+            || source.get_account(route, puuid),
+        )
+        .await;
+        metrics::record_api_result("account", region, &result);
+
+        let error = match result {
+            Ok(account) => return Ok(account),
+            Err(error) if is_cluster_failure(&error) => error,
+            Err(error) => return Err(error),
+        };
+
+        warn!(puuid, %route, ?error, "Account API query failed, trying next cluster.");
+        last_error = Some(error);
+    }
+
+    Err(last_error.expect("No account clusters to query"))
+}
 
 #[instrument(skip_all, fields(puuids = puuids.len()))]
 pub async fn update_riot_ids(
+    source: &dyn LeagueDataSource,
     puuids: &[String],
+    region: PlatformRoute,
     txn: &DatabaseTransaction,
 ) -> Result<Vec<riot_ids::ActiveModel>> {
     let t1 = Instant::now();
     info!("Getting account infos from Riot API...",);
 
-    let results = join_all(puuids.iter().map(|puuid| {
-        with_timeout(
-            Duration::from_secs(5),
-            RIOT_API
-                .account_v1()
-                .get_by_puuid(riven::consts::RegionalRoute::EUROPE, puuid),
-        )
-    }))
+    let results = join_all(
+        puuids
+            .iter()
+            .map(|puuid| get_account(source, region, puuid)),
+    )
     .await;
 
     info!(
@@ -39,7 +124,7 @@ pub async fn update_riot_ids(
         .iter()
         .zip(results.iter())
         .filter_map(|(puuid, result)| match result.as_ref() {
-            Ok(Ok(a)) => {
+            Ok(a) => {
                 if a.game_name.is_none() || a.tag_line.is_none() {
                     error!(puuid, account = ?a, "Missing game_name or tag_line for puuid, skipping.");
                     return None
@@ -51,12 +136,8 @@ pub async fn update_riot_ids(
                     ..Default::default()
                 })
             }
-            Ok(Err(e)) => {
-                error!(puuid, error = ?e, "An account API query failed.");
-                None
-            }
             Err(e) => {
-                error!(puuid, error = ?e, "An account API query timed out.");
+                error!(puuid, error = ?e, "An account API query failed.");
                 None
             }
         })
@@ -68,7 +149,7 @@ pub async fn update_riot_ids(
         "Upserting accounts into DB..."
     );
 
-    for chunk in riot_id_models.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in riot_id_models.chunks(config::get().insert_chunk_size) {
         riot_ids::Entity::insert_many(chunk.to_vec())
             .on_conflict(
                 OnConflict::column(riot_ids::Column::Puuid)
@@ -92,3 +173,103 @@ pub async fn update_riot_ids(
 
     Ok(riot_id_models)
 }
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::league_data_source::fake::FakeDataSource;
+    use crate::rate_budget::{Priority, RateBudget, RateLimit, RateLimits};
+
+    #[test]
+    fn queries_own_cluster_first() {
+        assert_eq!(
+            account_routes(PlatformRoute::KR),
+            vec![
+                RegionalRoute::ASIA,
+                RegionalRoute::AMERICAS,
+                RegionalRoute::EUROPE,
+                RegionalRoute::SEA,
+            ]
+        );
+        assert_eq!(
+            account_routes(PlatformRoute::NA1)[0],
+            RegionalRoute::AMERICAS
+        );
+        assert_eq!(
+            account_routes(PlatformRoute::EUW1)[0],
+            RegionalRoute::EUROPE
+        );
+        assert_eq!(account_routes(PlatformRoute::OC1)[0], RegionalRoute::SEA);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn falls_back_to_other_clusters() {
+        let source = FakeDataSource::new()
+            .with_account("puuid1", "Player", "KR1")
+            .with_unavailable_cluster(RegionalRoute::ASIA);
+
+        let account = get_account(&source, PlatformRoute::KR, "puuid1")
+            .await
+            .unwrap();
+
+        assert_eq!(account.puuid, "puuid1");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fails_when_every_cluster_fails() {
+        let source = ACCOUNT_CLUSTERS.into_iter().fold(
+            FakeDataSource::new().with_account("puuid1", "Player", "KR1"),
+            |source, route| source.with_unavailable_cluster(route),
+        );
+
+        assert!(get_account(&source, PlatformRoute::KR, "puuid1")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_fall_back_on_rejected_api_key() {
+        let source = FakeDataSource::new()
+            .with_account("puuid1", "Player", "KR1")
+            .with_cluster_error(RegionalRoute::ASIA, 403);
+
+        let error = get_account(&source, PlatformRoute::KR, "puuid1")
+            .await
+            .unwrap_err();
+
+        assert!(!is_cluster_failure(&error));
+        assert_eq!(source.account_queries(), vec![RegionalRoute::ASIA]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_rate_budget_without_timing_out() {
+        let window = config::get().timeouts.account * 10;
+        let rate_budget = RateBudget::new(RateLimits {
+            application: vec![],
+            league: vec![],
+            summoner: vec![],
+            account: vec![RateLimit {
+                requests: 2,
+                window,
+            }],
+            enrichment_share: 0.5,
+        });
+        rate_budget
+            .acquire("ASIA", Method::Account, Priority::Enrichment)
+            .await;
+        let source = FakeDataSource::new()
+            .with_account("puuid1", "Player", "KR1")
+            .with_rate_budget(rate_budget);
+        let t1 = tokio::time::Instant::now();
+
+        let account = get_account(&source, PlatformRoute::KR, "puuid1")
+            .await
+            .unwrap();
+
+        assert_eq!(account.puuid, "puuid1");
+        assert!(t1.elapsed() >= window);
+    }
+}
@@ -0,0 +1,100 @@
+use lazy_static::lazy_static;
+use tokio::select;
+use tokio::signal::ctrl_c;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{watch, Notify};
+use tokio::time::{sleep, Duration};
+use tracing::{info, warn};
+
+/// Exit code used when a second signal aborts the in-flight region updates.
+pub const FORCED_EXIT_CODE: i32 = 130;
+
+lazy_static! {
+    /// Notified by the second signal. Main stops waiting for the regions, flushes the logs and
+    /// exits.
+    static ref FORCED_EXIT: Notify = Notify::new();
+}
+
+async fn wait_for_signal() {
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+
+    select! {
+        _ = terminate.recv() => info!("Received SIGTERM."),
+        _ = ctrl_c() => info!("Received SIGINT."),
+    }
+}
+
+/// Listens for SIGTERM and SIGINT. The returned receiver flips to `true` on the first signal, which
+/// lets every region finish its current update before stopping. A second signal completes
+/// [`forced_exit`], so main exits without waiting, rolling back any open transaction.
+pub fn listen() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutting down after the in-flight region updates...");
+        sender.send_replace(true);
+
+        wait_for_signal().await;
+        warn!("Received second signal, exiting immediately.");
+        FORCED_EXIT.notify_one();
+    });
+
+    receiver
+}
+
+/// Completes once a second signal forces the exit.
+pub async fn forced_exit() {
+    FORCED_EXIT.notified().await;
+}
+
+pub fn is_requested(shutdown: &watch::Receiver<bool>) -> bool {
+    *shutdown.borrow()
+}
+
+/// Sleeps for `duration`, or until shutdown is requested.
+pub async fn sleep_unless_requested(duration: Duration, shutdown: &mut watch::Receiver<bool>) {
+    if is_requested(shutdown) {
+        return;
+    }
+
+    select! {
+        _ = sleep(duration) => {}
+        _ = shutdown.changed() => {}
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sleep_ends_on_shutdown() {
+        let (sender, mut receiver) = watch::channel(false);
+        let t1 = Instant::now();
+
+        tokio::spawn(async move {
+            sleep(Duration::from_millis(10)).await;
+            sender.send_replace(true);
+        });
+        sleep_unless_requested(Duration::from_secs(60), &mut receiver).await;
+
+        assert!(t1.elapsed() < Duration::from_secs(60));
+        assert!(is_requested(&receiver));
+    }
+
+    #[tokio::test]
+    async fn does_not_sleep_after_shutdown() {
+        let (_sender, mut receiver) = watch::channel(true);
+        let t1 = Instant::now();
+
+        sleep_unless_requested(Duration::from_secs(60), &mut receiver).await;
+
+        assert!(t1.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use riven::consts::PlatformRoute;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::{info, instrument};
+
+use crate::config::StatusServerConfig;
+use crate::error::PipelineError;
+use crate::metrics;
+
+lazy_static! {
+    /// Updated by every region after each update.
+    pub static ref REGION_STATUS: StatusRegistry = StatusRegistry::default();
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RegionStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    pub consecutive_failures: u32,
+    pub last_cycle_duration_ms: Option<u128>,
+    pub last_cycle_dodges: Option<usize>,
+}
+
+impl RegionStatus {
+    fn is_ready(&self, config: &StatusServerConfig, now: DateTime<Utc>) -> bool {
+        let max_update_age =
+            chrono::Duration::from_std(config.max_update_age).unwrap_or(chrono::Duration::MAX);
+
+        self.consecutive_failures < config.max_consecutive_failures
+            && self
+                .last_success
+                .is_some_and(|last_success| now - last_success <= max_update_age)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StatusRegistry {
+    regions: RwLock<BTreeMap<String, RegionStatus>>,
+}
+
+impl StatusRegistry {
+    /// Adds a region that has not finished an update yet, so it is reported as not ready.
+    pub fn register(&self, region: PlatformRoute) {
+        self.regions
+            .write()
+            .unwrap()
+            .entry(region.to_string())
+            .or_default();
+    }
+
+    pub fn record_success(&self, region: PlatformRoute, duration: Duration, dodges: usize) {
+        let mut regions = self.regions.write().unwrap();
+        let status = regions.entry(region.to_string()).or_default();
+
+        status.last_success = Some(Utc::now());
+        status.consecutive_failures = 0;
+        status.last_cycle_duration_ms = Some(duration.as_millis());
+        status.last_cycle_dodges = Some(dodges);
+    }
+
+    pub fn record_failure(&self, region: PlatformRoute, error: &PipelineError) {
+        let mut regions = self.regions.write().unwrap();
+        let status = regions.entry(region.to_string()).or_default();
+
+        status.last_error = Some(error.to_string());
+        status.last_error_at = Some(Utc::now());
+        status.consecutive_failures += 1;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, RegionStatus> {
+        self.regions.read().unwrap().clone()
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    registry: &'static StatusRegistry,
+    config: StatusServerConfig,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    ready: bool,
+    regions: BTreeMap<String, RegionStatus>,
+}
+
+/// The process is up and serving requests.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Every region finished an update recently and is not failing repeatedly.
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<StatusResponse>) {
+    let response = status_response(&state);
+    let code = if response.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, Json(response))
+}
+
+async fn status(State(state): State<AppState>) -> Json<StatusResponse> {
+    Json(status_response(&state))
+}
+
+/// Every metric in the Prometheus text format.
+async fn metrics() -> Result<String, (StatusCode, String)> {
+    metrics::encode().map_err(|error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", error)))
+}
+
+fn status_response(state: &AppState) -> StatusResponse {
+    let now = Utc::now();
+    let regions = state.registry.snapshot();
+    let ready = !regions.is_empty()
+        && regions
+            .values()
+            .all(|status| status.is_ready(&state.config, now));
+
+    StatusResponse { ready, regions }
+}
+
+fn router(registry: &'static StatusRegistry, config: StatusServerConfig) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status))
+        .route("/metrics", get(metrics))
+        .with_state(AppState { registry, config })
+}
+
+/// Binds the status server, so a taken port fails the startup, and serves it in the background
+/// until shutdown is requested.
+#[instrument(skip_all, fields(address = %config.address))]
+pub async fn serve(
+    registry: &'static StatusRegistry,
+    config: StatusServerConfig,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let listener = TcpListener::bind(config.address)
+        .await
+        .with_context(|| format!("Failed to bind status server to {}", config.address))?;
+    info!("Serving status endpoints.");
+
+    tokio::spawn(async move {
+        axum::serve(listener, router(registry, config))
+            .with_graceful_shutdown(async move {
+                let _ = shutdown.wait_for(|&requested| requested).await;
+            })
+            .await
+    });
+
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn config() -> StatusServerConfig {
+        StatusServerConfig {
+            address: "127.0.0.1:0".parse().unwrap(),
+            max_consecutive_failures: 2,
+            max_update_age: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn region_is_ready_after_successful_update() {
+        let registry = StatusRegistry::default();
+        registry.register(PlatformRoute::EUW1);
+        assert!(!registry.snapshot()["EUW1"].is_ready(&config(), Utc::now()));
+
+        registry.record_success(PlatformRoute::EUW1, Duration::from_millis(500), 3);
+        let status = &registry.snapshot()["EUW1"];
+        assert!(status.is_ready(&config(), Utc::now()));
+        assert_eq!(status.last_cycle_dodges, Some(3));
+        assert_eq!(status.last_cycle_duration_ms, Some(500));
+
+        let later = Utc::now() + chrono::Duration::minutes(2);
+        assert!(!status.is_ready(&config(), later));
+    }
+
+    #[test]
+    fn region_is_not_ready_after_repeated_failures() {
+        let registry = StatusRegistry::default();
+        registry.record_success(PlatformRoute::EUW1, Duration::from_millis(500), 0);
+
+        registry.record_failure(PlatformRoute::EUW1, &anyhow!("first").into());
+        assert!(registry.snapshot()["EUW1"].is_ready(&config(), Utc::now()));
+
+        registry.record_failure(PlatformRoute::EUW1, &anyhow!("second").into());
+        let status = &registry.snapshot()["EUW1"];
+        assert!(!status.is_ready(&config(), Utc::now()));
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some("second"));
+
+        registry.record_success(PlatformRoute::EUW1, Duration::from_millis(500), 0);
+        assert!(registry.snapshot()["EUW1"].is_ready(&config(), Utc::now()));
+    }
+
+    #[tokio::test]
+    async fn serves_status_endpoints() {
+        let registry: &'static StatusRegistry = Box::leak(Box::default());
+        registry.register(PlatformRoute::EUW1);
+        registry.register(PlatformRoute::NA1);
+        registry.record_success(PlatformRoute::EUW1, Duration::from_millis(500), 1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(registry, config())).await });
+        let url = |path: &str| format!("http://{address}{path}");
+
+        let response = reqwest::get(url("/healthz")).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let response = reqwest::get(url("/readyz")).await.unwrap();
+        assert_eq!(response.status().as_u16(), 503);
+
+        let response = reqwest::get(url("/metrics")).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+
+        let status: serde_json::Value = reqwest::get(url("/status"))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status["ready"], false);
+        assert_eq!(status["regions"]["EUW1"]["last_cycle_dodges"], 1);
+        assert!(status["regions"]["NA1"]["last_success"].is_null());
+
+        registry.record_success(PlatformRoute::NA1, Duration::from_millis(500), 0);
+        let response = reqwest::get(url("/readyz")).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use anyhow::Result;
 use futures::future::join_all;
@@ -8,15 +8,18 @@ use sea_orm::DatabaseTransaction;
 use sea_orm::{ActiveValue::Set, EntityTrait};
 use tracing::{error, info, instrument};
 
-use crate::util::with_timeout;
+use crate::rate_budget::Method;
+use crate::util::retry::retry_when_ready;
 use crate::{
-    config::INSERT_CHUNK_SIZE,
+    config,
     entities::{self, summoners},
-    riot_api::RIOT_API,
+    league_data_source::LeagueDataSource,
+    metrics,
 };
 
 #[instrument(skip_all, fields(summoners = summoner_ids.len()))]
 pub async fn upsert_summoners(
+    source: &dyn LeagueDataSource,
     summoner_ids: &[&str],
     region: PlatformRoute,
     txn: &DatabaseTransaction,
@@ -24,13 +27,20 @@ pub async fn upsert_summoners(
     let t1 = Instant::now();
     info!("Getting summoner info from league API for summoners...");
 
+    let config = config::get();
+    let route = region.to_string();
     let results = join_all(summoner_ids.iter().map(|s_id| {
-        with_timeout(
-            Duration::from_secs(10),
-            RIOT_API.summoner_v4().get_by_summoner_id(region, s_id),
+        retry_when_ready(
+            &config.retry,
+            config.timeouts.summoner,
+            || source.acquire_budget(&route, Method::Summoner),
+            || source.get_summoner(region, s_id),
         )
     }))
     .await;
+    for result in &results {
+        metrics::record_api_result("summoner", region, result);
+    }
 
     info!(
         perf = t1.elapsed().as_millis(),
@@ -42,7 +52,7 @@ pub async fn upsert_summoners(
     let summoner_models: Vec<entities::summoners::ActiveModel> = results
         .iter()
         .filter_map(|r| match r.as_ref() {
-            Ok(Ok(s)) => Some(summoners::ActiveModel {
+            Ok(s) => Some(summoners::ActiveModel {
                 puuid: Set(s.puuid.clone()),
                 summoner_id: Set(Some(s.id.clone())),
                 region: Set(region.to_string()),
@@ -51,12 +61,8 @@ pub async fn upsert_summoners(
                 summoner_level: Set(s.summoner_level),
                 ..Default::default()
             }),
-            Ok(Err(e)) => {
-                error!(error = ?e, "A summoner API query failed");
-                None
-            }
             Err(e) => {
-                error!(error = ?e, "A summoner API query timed out");
+                error!(error = ?e, "A summoner API query failed");
                 None
             }
         })
@@ -68,7 +74,7 @@ pub async fn upsert_summoners(
         "Upserting summoners into DB...",
     );
 
-    for chunk in summoner_models.chunks(INSERT_CHUNK_SIZE) {
+    for chunk in summoner_models.chunks(config::get().insert_chunk_size) {
         summoners::Entity::insert_many(chunk.to_vec())
             .on_conflict(
                 OnConflict::column(summoners::Column::Puuid)
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Instant;
+
+use anyhow::{bail, Result};
+use tokio::sync::watch;
+use tokio::time::Duration;
+use tracing::{error, info, instrument};
+
+use crate::{metrics, shutdown};
+
+/// How often and how fast a crashed task is restarted.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Wait before the first restart, doubled after every further crash.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// More crashes than this within `crash_window` count as a crash loop and stop the supervisor.
+    /// A task that ran for longer than `crash_window` is restarted with the initial backoff again.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5 * 60),
+            max_crashes: 5,
+            crash_window: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// Runs the task created by `spawn_task` until it returns, restarting it whenever it panics. Fails
+/// if the task is caught in a crash loop. Restarts are counted with `name`, the region of the task,
+/// as the label.
+#[instrument(skip(policy, shutdown, spawn_task))]
+pub async fn supervise<F, Fut>(
+    name: &str,
+    policy: &RestartPolicy,
+    mut shutdown: watch::Receiver<bool>,
+    mut spawn_task: F,
+) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut crashes: VecDeque<Instant> = VecDeque::new();
+    let mut total_crashes = 0;
+    let mut backoff = policy.initial_backoff;
+
+    loop {
+        let started_at = Instant::now();
+        let error = match tokio::spawn(spawn_task()).await {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        let now = Instant::now();
+        total_crashes += 1;
+        crashes.push_back(now);
+        while crashes
+            .front()
+            .is_some_and(|&crash| now - crash > policy.crash_window)
+        {
+            crashes.pop_front();
+        }
+
+        if crashes.len() > policy.max_crashes {
+            error!(?error, total_crashes, "Task is crash looping, giving up.");
+            bail!(
+                "{} crashed {} times within {:?}",
+                name,
+                crashes.len(),
+                policy.crash_window
+            );
+        }
+
+        if now - started_at > policy.crash_window {
+            backoff = policy.initial_backoff;
+        }
+
+        error!(
+            ?error,
+            total_crashes,
+            backoff = backoff.as_millis(),
+            "Task crashed, restarting..."
+        );
+        metrics::record_region_restart(name);
+
+        shutdown::sleep_unless_requested(backoff, &mut shutdown).await;
+        if shutdown::is_requested(&shutdown) {
+            info!("Not restarting task, shutdown requested.");
+            return Ok(());
+        }
+
+        backoff = (backoff * 2).min(policy.max_backoff);
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            max_crashes: 3,
+            crash_window: Duration::from_secs(60),
+        }
+    }
+
+    /// Spawns a task that panics on its first `crashes` runs and returns after that.
+    fn crashing_task(
+        runs: Arc<AtomicUsize>,
+        crashes: usize,
+    ) -> impl FnMut() -> futures::future::BoxFuture<'static, ()> {
+        move || {
+            let runs = runs.clone();
+            Box::pin(async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < crashes {
+                    panic!("crash");
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn restarts_crashed_task() {
+        let (_sender, shutdown) = watch::channel(false);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let result = supervise(
+            "restarted_task",
+            &policy(),
+            shutdown,
+            crashing_task(runs.clone(), 3),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+        assert!(metrics::encode()
+            .unwrap()
+            .contains("dodgetracker_region_restarts_total{region=\"restarted_task\"} 3"));
+    }
+
+    #[tokio::test]
+    async fn fails_on_crash_loop() {
+        let (_sender, shutdown) = watch::channel(false);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let result = supervise(
+            "task",
+            &policy(),
+            shutdown,
+            crashing_task(runs.clone(), usize::MAX),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn does_not_restart_after_shutdown() {
+        let (_sender, shutdown) = watch::channel(true);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let result = supervise("task", &policy(), shutdown, crashing_task(runs.clone(), 1)).await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
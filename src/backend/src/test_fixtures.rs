@@ -0,0 +1,83 @@
+//! Ladder entries and API errors shared by the tests. Anything a test doesn't pass is set to a
+//! value no test depends on, override it with struct update syntax if it matters.
+
+use chrono::Utc;
+use riven::consts::{Division, RegionalRoute};
+use riven::models::league_v4::LeagueItem;
+use riven::{RiotApi, RiotApiConfig, RiotApiError};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use crate::entities::apex_tier_players;
+use crate::entities::sea_orm_active_enums::RankTierEnum;
+
+/// A stored EUW1 Master player, updated just now.
+pub fn db_player(
+    summoner_id: &str,
+    current_lp: i64,
+    wins: i64,
+    losses: i64,
+) -> apex_tier_players::Model {
+    apex_tier_players::Model {
+        summoner_id: summoner_id.to_string(),
+        summoner_name: None,
+        region: "EUW1".to_string(),
+        current_lp,
+        wins,
+        losses,
+        rank_tier: RankTierEnum::Master,
+        created_at: Utc::now().into(),
+        updated_at: Utc::now().into(),
+    }
+}
+
+/// An active player of a League API league list.
+pub fn league_item(summoner_id: &str, league_points: i32, wins: i32, losses: i32) -> LeagueItem {
+    LeagueItem {
+        summoner_id: summoner_id.to_string(),
+        league_points,
+        wins,
+        losses,
+        fresh_blood: false,
+        mini_series: None,
+        inactive: false,
+        veteran: false,
+        hot_streak: false,
+        rank: Division::I,
+    }
+}
+
+/// The error riven returns for a response with `status` and `headers`. riven can't build one
+/// without a response, so a local server answers a single account query with it.
+pub async fn riot_api_error(status: u16, headers: &[(&str, &str)]) -> RiotApiError {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    let response = format!(
+        "HTTP/1.1 {} Error\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n",
+        status, headers
+    );
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        // The query has no body, so it ends with the empty line after the headers
+        let mut line = String::new();
+        while stream.read_line(&mut line).await.unwrap() > 2 {
+            line.clear();
+        }
+        stream.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    let config = RiotApiConfig::with_key("RGAPI-test")
+        .set_base_url(format!("http://{}", address))
+        .set_retries(0);
+    RiotApi::new(config)
+        .account_v1()
+        .get_by_puuid(RegionalRoute::EUROPE, "puuid")
+        .await
+        .unwrap_err()
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use futures::Future;
+
+pub mod retry;
+
+/// Returned by [`with_timeout`], so timeouts can be told apart from the errors of the future.
+#[derive(Debug)]
+pub struct TimeoutError(Duration);
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Future timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+pub async fn with_timeout<F, T>(timeout: Duration, future: F) -> Result<T>
+where
+    F: Future<Output = T>,
+{
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => Ok(result),
+        Err(_) => Err(TimeoutError(timeout).into()),
+    }
+}
+
+pub fn is_timeout(error: &Error) -> bool {
+    error.downcast_ref::<TimeoutError>().is_some()
+}
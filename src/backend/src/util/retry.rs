@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use futures::Future;
+use riven::reqwest::header::RETRY_AFTER;
+use riven::RiotApiError;
+use tokio::time::sleep;
+use tracing::warn;
+
+use super::{is_timeout, with_timeout};
+
+/// How often and how fast a failed API query is retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Including the first attempt.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled after every further attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The fraction of every wait that is randomized, so queries that failed together don't retry
+    /// together. Between 0 and 1.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(2),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn jittered(&self, backoff: Duration) -> Duration {
+        backoff.mul_f64(1.0 - self.jitter * rand::random::<f64>())
+    }
+}
+
+fn is_retryable_status(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+/// Timeouts, rate limits, server errors and failed connections are worth another attempt. Anything
+/// else, like a 404, fails the same way again.
+pub fn is_retryable(error: &Error) -> bool {
+    if is_timeout(error) {
+        return true;
+    }
+
+    error.chain().any(|cause| {
+        if let Some(error) = cause.downcast_ref::<RiotApiError>() {
+            // Riven errors without a status never got a response
+            return error
+                .status_code()
+                .is_none_or(|status| is_retryable_status(status.as_u16()));
+        }
+
+        cause.downcast_ref::<reqwest::Error>().is_some_and(|error| {
+            error.is_timeout()
+                || error.is_connect()
+                || error
+                    .status()
+                    .is_some_and(|status| is_retryable_status(status.as_u16()))
+        })
+    })
+}
+
+/// How long the Riot API asked to wait with the `Retry-After` header of a 429 response.
+fn retry_after(error: &Error) -> Option<Duration> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<RiotApiError>())
+        .filter(|error| {
+            error
+                .status_code()
+                .is_some_and(|status| status.as_u16() == 429)
+        })
+        .and_then(|error| error.response())
+        .and_then(|response| response.headers().get(RETRY_AFTER))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Runs `operation` with `timeout` until it succeeds, fails with an error that is not retryable, or
+/// `policy.max_attempts` is reached. Returns the last error. A rate limited query is not retried
+/// before its `Retry-After` has passed, even if the backoff is shorter.
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, timeout: Duration, operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    retry_when_ready(policy, timeout, || async {}, operation).await
+}
+
+/// Like [`retry`], but waits for `ready` before every attempt, e.g. for the rate budget. The wait
+/// doesn't count towards `timeout`, so a deferred attempt waits instead of timing out.
+pub async fn retry_when_ready<T, R, ReadyFut, F, Fut>(
+    policy: &RetryPolicy,
+    timeout: Duration,
+    mut ready: R,
+    mut operation: F,
+) -> Result<T>
+where
+    R: FnMut() -> ReadyFut,
+    ReadyFut: Future<Output = ()>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+
+    loop {
+        ready().await;
+
+        let error = match with_timeout(timeout, operation()).await {
+            Ok(Ok(value)) => return Ok(value),
+            Ok(Err(error)) | Err(error) => error,
+        };
+
+        if attempt >= policy.max_attempts || !is_retryable(&error) {
+            return Err(error);
+        }
+
+        let delay = policy
+            .jittered(backoff)
+            .max(retry_after(&error).unwrap_or_default());
+        warn!(
+            attempt,
+            delay = delay.as_millis(),
+            ?error,
+            "Query failed, retrying..."
+        );
+        sleep(delay).await;
+
+        backoff = (backoff * 2).min(policy.max_backoff);
+        attempt += 1;
+    }
+}
+
+// ----------------------------------------------------------------------------------------------------------------------------------------
+// ----------------------------------------------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use anyhow::anyhow;
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::test_fixtures::riot_api_error;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            jitter: 0.5,
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn retries_timeouts_until_success() {
+        let attempts = AtomicU32::new(0);
+
+        let result = retry(&policy(), TIMEOUT, || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                sleep(Duration::from_secs(60)).await;
+            }
+            Ok(42)
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<()> = retry(&policy(), TIMEOUT, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            sleep(Duration::from_secs(60)).await;
+            Ok(())
+        })
+        .await;
+
+        assert!(is_timeout(&result.unwrap_err()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_permanent_errors() {
+        let attempts = AtomicU32::new(0);
+
+        let result: Result<()> = retry(&policy(), TIMEOUT, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(anyhow!("invalid response"))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn waits_for_retry_after_of_rate_limited_queries() {
+        let attempts = AtomicU32::new(0);
+        let t1 = Instant::now();
+
+        let result = retry(&policy(), Duration::from_secs(5), || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(riot_api_error(429, &[("Retry-After", "1")]).await.into());
+            }
+            Ok(())
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(t1.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_shortens_backoff() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.jittered(Duration::from_millis(1000));
+            assert!(delay > Duration::from_millis(500) && delay <= Duration::from_millis(1000));
+        }
+    }
+}
@@ -0,0 +1,7 @@
+DO $$ BEGIN
+ CREATE TYPE "dodgetracker"."dodge_type_enum" AS ENUM('FIRST_OFFENCE', 'REPEAT_OFFENCE', 'ZERO_LP_FLOOR', 'UNKNOWN');
+EXCEPTION
+ WHEN duplicate_object THEN null;
+END $$;
+--> statement-breakpoint
+ALTER TABLE "dodgetracker"."dodges" ADD COLUMN "dodge_type" "dodgetracker"."dodge_type_enum" DEFAULT 'UNKNOWN' NOT NULL;
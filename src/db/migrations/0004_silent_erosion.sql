@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS "dodgetracker"."decays" (
+	"decay_id" bigserial PRIMARY KEY NOT NULL,
+	"summoner_id" varchar(255) NOT NULL,
+	"region" varchar(10) NOT NULL,
+	"lp_before" bigint NOT NULL,
+	"lp_after" bigint NOT NULL,
+	"created_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"updated_at" timestamp with time zone DEFAULT now() NOT NULL,
+	"at_wins" bigint NOT NULL,
+	"at_losses" bigint NOT NULL,
+	"rank_tier" "dodgetracker"."rank_tier_enum" NOT NULL
+);
+--> statement-breakpoint
+CREATE INDEX IF NOT EXISTS "decays_summoner_id_region_created_at" ON "dodgetracker"."decays" USING btree ("summoner_id","region","created_at");
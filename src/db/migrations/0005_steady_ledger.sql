@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS "dodgetracker"."lp_history" (
+	"id" bigserial PRIMARY KEY NOT NULL,
+	"summoner_id" varchar(255) NOT NULL,
+	"region" varchar(10) NOT NULL,
+	"lp" bigint NOT NULL,
+	"wins" bigint NOT NULL,
+	"losses" bigint NOT NULL,
+	"rank_tier" "dodgetracker"."rank_tier_enum" NOT NULL,
+	"at_time" timestamp with time zone DEFAULT now() NOT NULL
+);
+--> statement-breakpoint
+CREATE INDEX IF NOT EXISTS "lp_history_summoner_id_region_at_time" ON "dodgetracker"."lp_history" USING btree ("summoner_id","region","at_time");
@@ -0,0 +1 @@
+ALTER TABLE "dodgetracker"."dodges" ADD COLUMN "masked_by_game" boolean DEFAULT false NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE "dodgetracker"."dodges" ADD COLUMN "confidence" double precision DEFAULT 1 NOT NULL;--> statement-breakpoint
+ALTER TABLE "dodgetracker"."dodges" ADD COLUMN "confidence_reasons" text[] DEFAULT '{}'::text[] NOT NULL;
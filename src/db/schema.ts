@@ -1,9 +1,13 @@
+import { sql } from "drizzle-orm";
 import {
   bigint,
   bigserial,
+  boolean,
+  doublePrecision,
   index,
   pgSchema,
   primaryKey,
+  text,
   timestamp,
   uniqueIndex,
   varchar,
@@ -15,6 +19,12 @@ export const rankTierEnum = dodgetracker.enum("rank_tier_enum", [
   "GRANDMASTER",
   "MASTER",
 ]);
+export const dodgeTypeEnum = dodgetracker.enum("dodge_type_enum", [
+  "FIRST_OFFENCE",
+  "REPEAT_OFFENCE",
+  "ZERO_LP_FLOOR",
+  "UNKNOWN",
+]);
 
 export const demotions = dodgetracker.table(
   "demotions",
@@ -163,6 +173,15 @@ export const dodges = dodgetracker.table(
     // You can use { mode: "bigint" } if numbers are exceeding js number limitations
     atLosses: bigint("at_losses", { mode: "number" }).notNull(),
     rankTier: rankTierEnum("rank_tier").notNull(),
+    dodgeType: dodgeTypeEnum("dodge_type").default("UNKNOWN").notNull(),
+    // The player finished a game before the dodge was seen, so `lpAfter` is estimated from the penalty
+    maskedByGame: boolean("masked_by_game").default(false).notNull(),
+    // Between 0 and 1, see `confidenceReasons` for what lowered it
+    confidence: doublePrecision("confidence").default(1).notNull(),
+    confidenceReasons: text("confidence_reasons")
+      .array()
+      .default(sql`'{}'::text[]`)
+      .notNull(),
   },
   (table) => {
     return {
@@ -185,6 +204,37 @@ export const dodges = dodgetracker.table(
   },
 );
 
+export const decays = dodgetracker.table(
+  "decays",
+  {
+    decayId: bigserial("decay_id", { mode: "bigint" }).primaryKey().notNull(),
+    summonerId: varchar("summoner_id", { length: 255 }).notNull(),
+    region: varchar("region", { length: 10 }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    lpBefore: bigint("lp_before", { mode: "number" }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    lpAfter: bigint("lp_after", { mode: "number" }).notNull(),
+    createdAt: timestamp("created_at", { withTimezone: true, mode: "date" })
+      .defaultNow()
+      .notNull(),
+    updatedAt: timestamp("updated_at", { withTimezone: true, mode: "date" })
+      .defaultNow()
+      .notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    atWins: bigint("at_wins", { mode: "number" }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    atLosses: bigint("at_losses", { mode: "number" }).notNull(),
+    rankTier: rankTierEnum("rank_tier").notNull(),
+  },
+  (table) => {
+    return {
+      decaysSummonerIdRegionCreatedAt: index(
+        "decays_summoner_id_region_created_at",
+      ).using("btree", table.summonerId, table.region, table.createdAt),
+    };
+  },
+);
+
 export const playerCounts = dodgetracker.table("player_counts", {
   id: bigserial("id", { mode: "bigint" }).primaryKey().notNull(),
   region: varchar("region", { length: 10 }).notNull(),
@@ -196,6 +246,32 @@ export const playerCounts = dodgetracker.table("player_counts", {
   rankTier: rankTierEnum("rank_tier").notNull(),
 });
 
+export const lpHistory = dodgetracker.table(
+  "lp_history",
+  {
+    id: bigserial("id", { mode: "bigint" }).primaryKey().notNull(),
+    summonerId: varchar("summoner_id", { length: 255 }).notNull(),
+    region: varchar("region", { length: 10 }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    lp: bigint("lp", { mode: "number" }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    wins: bigint("wins", { mode: "number" }).notNull(),
+    // You can use { mode: "bigint" } if numbers are exceeding js number limitations
+    losses: bigint("losses", { mode: "number" }).notNull(),
+    rankTier: rankTierEnum("rank_tier").notNull(),
+    atTime: timestamp("at_time", { withTimezone: true, mode: "date" })
+      .defaultNow()
+      .notNull(),
+  },
+  (table) => {
+    return {
+      lpHistorySummonerIdRegionAtTime: index(
+        "lp_history_summoner_id_region_at_time",
+      ).using("btree", table.summonerId, table.region, table.atTime),
+    };
+  },
+);
+
 export const apexTierPlayers = dodgetracker.table(
   "apex_tier_players",
   {